@@ -8,11 +8,25 @@ use crate::rvc;
 
 use super::data::song;
 
+pub mod config;
+pub mod library;
+pub mod lyrics;
+pub mod player;
+pub mod restore;
+
 #[derive(Debug)]
 pub enum SongError {
     Guild,
     VoiceChannel,
     VoiceConnection,
+    QueuePosition,
+    Permission,
+    NotPlaying,
+    NotListening,
+    QueueLimit(usize),
+    UserQueueLimit(usize),
+    DurationLimit(std::time::Duration),
+    UnknownDuration(std::time::Duration),
 }
 
 impl std::fmt::Display for SongError {
@@ -21,6 +35,18 @@ impl std::fmt::Display for SongError {
             Self::Guild => f.write_str("Song feature requires guild to use voice chat."),
             Self::VoiceChannel => f.write_str("Cannot find voice channel from request."),
             Self::VoiceConnection => f.write_str("Bot is not connected to the voice channel."),
+            Self::QueuePosition => f.write_str("There is no song at that position in the queue."),
+            Self::Permission => f.write_str("You don't have permission to do that."),
+            Self::NotPlaying => f.write_str("Nothing is playing."),
+            Self::NotListening => f.write_str("You must be in the bot's voice channel to vote."),
+            Self::QueueLimit(max) => write!(f, "The queue is full. (max {} songs)", max),
+            Self::UserQueueLimit(max) => write!(f, "You have too many songs in the queue. (max {} songs)", max),
+            Self::DurationLimit(max) => write!(f, "The song is too long. (max {})", song::format_duration(*max)),
+            Self::UnknownDuration(max) => write!(
+                f,
+                "The length of the song is unknown, such as a live stream. (max {})",
+                song::format_duration(*max)
+            ),
         }
     }
 }
@@ -67,11 +93,231 @@ struct TrackEndHandler {
 
 #[async_trait]
 impl songbird::EventHandler for TrackEndHandler {
-    async fn act(&self, _: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+    async fn act(&self, ctx: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        let ended_tracks: Vec<_> = match ctx {
+            songbird::EventContext::Track(tracks) => tracks.iter().map(|(_, handle)| handle.uuid()).collect(),
+            _ => Vec::new(),
+        };
+
+        let guild_data = data::Storage::guild(&self.context, self.guild_id).await;
+        let replay = {
+            let mut guild_data = guild_data.lock().await;
+
+            // the track may have been stopped by skipping or crossfaded, and the next one already started
+            let is_now = guild_data
+                .song_now
+                .as_ref()
+                .and_then(|now| now.track())
+                .is_some_and(|track| ended_tracks.contains(&track.uuid()));
+            if !is_now {
+                return None;
+            }
+
+            // fading out before the next song is ready would leave a gap instead of an overlap
+            if guild_data.song_prefetch.as_ref().is_some_and(|prefetch| !prefetch.is_ready()) {
+                return None;
+            }
+
+            let replay = guild_data.song_now_finish(&self.context);
+            guild_data.save().await.ok();
+            replay
+        };
+
+        play_next_internal(&self.context, self.guild_id, replay, std::time::Duration::ZERO).await.ok();
+        None
+    }
+}
+
+/// Fade applied when a song is skipped or stopped.
+const FADE_DURATION: std::time::Duration = std::time::Duration::from_millis(500);
+const FADE_STEP: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Ramps the volume of `track` linearly from `from` to `to`.
+async fn fade(
+    track: &songbird::tracks::TrackHandle,
+    from: f32,
+    to: f32,
+    duration: std::time::Duration,
+) -> Result<(), Error> {
+    let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        tokio::time::sleep(duration / steps).await;
+        track.set_volume(from + (to - from) * step as f32 / steps as f32)?;
+    }
+    Ok(())
+}
+
+fn fade_in_nowait(track: songbird::tracks::TrackHandle, volume: f32, duration: std::time::Duration) {
+    tokio::spawn(async move {
+        fade(&track, 0.0, volume, duration).await.ok();
+    });
+}
+
+/// Fades `track` out and stops it. Paused tracks are stopped right away.
+fn fade_out_nowait(track: songbird::tracks::TrackHandle, duration: std::time::Duration) {
+    tokio::spawn(async move {
+        let volume = track
+            .get_info()
+            .await
+            .ok()
+            .filter(|info| info.playing == songbird::tracks::PlayMode::Play)
+            .map(|info| info.volume);
+        if let Some(volume) = volume {
+            fade(&track, volume, 0.0, duration).await.ok();
+        }
+        track.stop().ok();
+    });
+}
+
+const CROSSFADE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Starts the next song under the current one when it is about to end.
+struct CrossfadeHandler {
+    context: serenity::Context,
+    guild_id: serenity::GuildId,
+    crossfade: std::time::Duration,
+}
+
+#[async_trait]
+impl songbird::EventHandler for CrossfadeHandler {
+    async fn act(&self, ctx: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        let (track, position) = match ctx {
+            songbird::EventContext::Track(tracks) => {
+                let (state, track) = tracks.first()?;
+                (*track, song::TrackOffset::song_position(track, state.position).await)
+            }
+            _ => return None,
+        };
+        let speed = song::TrackOffset::of(track).await.map_or(1.0, |offset| offset.speed);
+
+        let guild_data = data::Storage::guild(&self.context, self.guild_id).await;
+        let (replay, remaining) = {
+            let mut guild_data = guild_data.lock().await;
+            if !guild_data.song_now_is(track) {
+                return Some(songbird::Event::Cancel);
+            }
+
+            // songs of unknown length, such as live streams, are never crossfaded
+            let now = guild_data.song_now.as_ref()?.request();
+            let duration = now.metadata.lock().unwrap().as_ref().and_then(|metadata| metadata.duration)?;
+            let remaining = duration.saturating_sub(position).div_f64(speed);
+            if remaining > self.crossfade {
+                return None;
+            }
+
+            // fading out before the next song is ready would leave a gap instead of an overlap
+            if guild_data.song_prefetch.as_ref().is_some_and(|prefetch| !prefetch.is_ready()) {
+                return None;
+            }
+
+            let replay = guild_data.song_now_finish(&self.context);
+            guild_data.save().await.ok();
+            (replay, remaining)
+        };
+
+        fade_out_nowait(track.clone(), remaining);
+        play_next_internal(&self.context, self.guild_id, replay, remaining).await.ok();
+        Some(songbird::Event::Cancel)
+    }
+}
+
+const ICY_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Shows the title announced by a radio stream in the activity and the player.
+struct IcyHandler {
+    context: serenity::Context,
+    request: Arc<data::song::Request>,
+    shown: std::sync::Mutex<Option<String>>,
+}
+
+#[async_trait]
+impl songbird::EventHandler for IcyHandler {
+    async fn act(&self, ctx: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        let track = match ctx {
+            songbird::EventContext::Track(tracks) => tracks.first()?.1,
+            _ => return None,
+        };
+
+        let title = self.request.live_title.get()?;
+        if self.shown.lock().unwrap().as_ref() == Some(&title) {
+            return None;
+        }
+
+        let guild_data = data::Storage::guild(&self.context, self.request.guild_id).await;
+        let repeat = {
+            let guild_data = guild_data.lock().await;
+            if !guild_data.song_now_is(track) {
+                return Some(songbird::Event::Cancel);
+            }
+            guild_data.song_repeat
+        };
+
+        *self.shown.lock().unwrap() = Some(title.clone());
+        set_activity(&self.context, repeat, title);
+        player::update_nowait(&self.context, self.request.guild_id);
+        None
+    }
+}
+
+const PREFETCH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const PREFETCH_LEAD: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Starts resolving the next request when the current song is about to end,
+/// and restarts it whenever the request to be played next changes.
+struct PrefetchHandler {
+    context: serenity::Context,
+    guild_id: serenity::GuildId,
+}
+
+#[async_trait]
+impl songbird::EventHandler for PrefetchHandler {
+    async fn act(&self, ctx: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        let (track, position) = match ctx {
+            songbird::EventContext::Track(tracks) => {
+                let (state, track) = tracks.first()?;
+                (*track, song::TrackOffset::song_position(track, state.position).await)
+            }
+            _ => return None,
+        };
+
         let guild_data = data::Storage::guild(&self.context, self.guild_id).await;
-        guild_data.lock().await.song_now_complete(&self.context);
+        let (now, repeat, scheduler, recent_authors, queue, filter) = {
+            let guild_data = guild_data.lock().await;
+            if !guild_data.song_now_is(track) {
+                return Some(songbird::Event::Cancel);
+            }
+            (
+                guild_data.song_now.as_ref()?.request(),
+                guild_data.song_repeat,
+                guild_data.song_scheduler,
+                guild_data.song_recent_authors(),
+                guild_data.song_queue.iter().cloned().collect::<Vec<_>>(),
+                guild_data.song_filter,
+            )
+        };
+
+        // songs of unknown length, such as live streams, may never end
+        let duration = now.metadata.lock().unwrap().as_ref().and_then(|metadata| metadata.duration)?;
+        if duration > position + PREFETCH_LEAD {
+            return None;
+        }
 
-        next_internal(&self.context, self.guild_id).await.ok();
+        let next = match repeat {
+            song::RepeatMode::Track => Some(now),
+            _ => {
+                match scheduler.select(&queue, &recent_authors) {
+                    Some(index) => Some(queue[index].clone()),
+                    None if repeat == song::RepeatMode::Queue => Some(now),
+                    None => None,
+                }
+            }
+        }?;
+
+        // the previous prefetch is aborted if the queue order has changed since
+        let mut guild_data = guild_data.lock().await;
+        if !guild_data.song_prefetch.as_ref().is_some_and(|prefetch| prefetch.is_for(&next, &filter)) {
+            guild_data.song_prefetch = Some(song::Prefetch::new(&self.context, next, filter));
+        }
         None
     }
 }
@@ -89,6 +335,8 @@ impl songbird::EventHandler for DisconnectHandler {
         let mut guild_data = guild_data.lock().await;
         guild_data.song_now_cancel(&self.context);
         guild_data.song_queue_clear(&self.context);
+        player::update_nowait(&self.context, self.guild_id);
+        restore::save_nowait(&self.context, self.guild_id);
         None
     }
 }
@@ -125,35 +373,41 @@ pub async fn join_internal(
     };
 
     match channel_id {
-        Some(channel_id) => {
-            let manager = songbird::get(ctx).await.unwrap().clone();
-            if let Some(call) = manager.get(guild_id) {
-                if call.lock().await.current_channel()
-                    == Some(songbird::id::ChannelId::from(channel_id))
-                {
-                    return Ok(call);
-                }
-            }
-
-            let call_ptr = manager.join(guild_id, channel_id).await?;
-
-            // Initialize Call
-            {
-                let mut call = call_ptr.lock().await;
-                call.deafen(true).await?;
-                call.add_global_event(
-                    songbird::Event::Core(songbird::CoreEvent::DriverDisconnect),
-                    DisconnectHandler {
-                        context: ctx.clone(),
-                        guild_id,
-                    },
-                )
-            }
+        Some(channel_id) => join_channel_internal(ctx, guild_id, channel_id).await,
+        None => Err(SongError::VoiceChannel.into()),
+    }
+}
 
-            Ok(call_ptr)
+pub async fn join_channel_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    channel_id: serenity::ChannelId,
+) -> Result<Arc<tokio::sync::Mutex<songbird::Call>>, Error> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    if let Some(call) = manager.get(guild_id) {
+        if call.lock().await.current_channel()
+            == Some(songbird::id::ChannelId::from(channel_id))
+        {
+            return Ok(call);
         }
-        None => Err(SongError::VoiceChannel.into()),
     }
+
+    let call_ptr = manager.join(guild_id, channel_id).await?;
+
+    // Initialize Call
+    {
+        let mut call = call_ptr.lock().await;
+        call.deafen(true).await?;
+        call.add_global_event(
+            songbird::Event::Core(songbird::CoreEvent::DriverDisconnect),
+            DisconnectHandler {
+                context: ctx.clone(),
+                guild_id,
+            },
+        )
+    }
+
+    Ok(call_ptr)
 }
 
 pub async fn join_or_get(
@@ -175,43 +429,102 @@ pub async fn join_or_get(
 
 
 
-pub async fn play_internal(
+fn set_activity(ctx: &serenity::Context, repeat: song::RepeatMode, title: String) {
+    match repeat.emoji() {
+        Some(emoji) => ctx.set_activity(Some(serenity::ActivityData::listening(format!("{} {}", emoji, title)))),
+        None => ctx.set_activity(Some(serenity::ActivityData::listening(title))),
+    }
+}
+
+/// Plays `request` from `start`, with the filter of the guild.
+/// The new track fades in over `fade_in`, while the track being replaced fades out.
+pub async fn play_from_internal(
     ctx: &serenity::Context,
     request: Arc<data::song::Request>,
+    start: std::time::Duration,
+    fade_in: std::time::Duration,
 ) -> Result<Option<songbird::tracks::TrackHandle>, Error> {
     let guild_data = data::Storage::guild(ctx, request.guild_id).await;
-    guild_data.lock().await.song_now = Some(data::song::Now::Waiting{
-        request: request.clone()
-    });
+    let (filter, previous) = {
+        let mut guild_data = guild_data.lock().await;
+        let previous = guild_data.song_now.as_ref().and_then(|now| now.track());
+        let is_restart = guild_data.song_now.as_ref().is_some_and(|now| Arc::ptr_eq(&now.request(), &request));
+        guild_data.song_now = Some(data::song::Now::Waiting{
+            request: request.clone()
+        });
+        if !is_restart {
+            guild_data.song_skip_votes.clear();
+        }
+        (guild_data.song_filter, previous)
+    };
 
-    let (handle, title_future) = {
+    let (handle, title_future, crossfade) = {
    
-        let (input, title_future) = match request.source.get_input(ctx, request.locale.as_deref()).await? {
+        let prefetch = guild_data.lock().await.song_prefetch.take();
+        // a filtered prefetch starts from the beginning of the song
+        let prefetch = prefetch.filter(|prefetch| {
+            prefetch.is_for(&request, &filter) && (filter.is_off() || start.is_zero())
+        });
+        let prefetched = match prefetch {
+            Some(prefetch) => prefetch.input().await.ok(),
+            None => None,
+        };
+        let input_result = match prefetched {
+            Some(input_result) => input_result,
+            // the prefetch may also have failed for a passing reason, so it is resolved once more
+            None if filter.is_off() => request.get_input(ctx).await?,
+            None => request.get_filtered_input(ctx, &filter, start).await?,
+        };
+
+        let (input, title_future) = match input_result {
             song::InputResult::Input(input, title_future) => (input, title_future),
             song::InputResult::Canceled => {
                 return Ok(None)
             },
         };
         
+        let volume = guild_data.lock().await.song_config.volume as f32 / 100.0;
         let handle = {
             let call = join_or_get(ctx, request.guild_id, Some(request.author_id)).await?;
             let mut call = call.lock().await;
-            call.play_only_input(input)
+            // the previous track keeps playing under the new one until it fades out
+            let track = songbird::tracks::Track::from(input).volume(if fade_in.is_zero() { volume } else { 0.0 });
+            call.play(track)
         };
+        if let Some(previous) = previous {
+            fade_out_nowait(previous, fade_in);
+        }
+        if !fade_in.is_zero() {
+            fade_in_nowait(handle.clone(), volume, fade_in);
+        }
         
-        guild_data.lock().await.song_now = Some(data::song::Now::Playing{
-            track: handle.clone(),
-            request: request.clone()
-        });
+        if !filter.is_off() {
+            let offset = song::TrackOffset {
+                start,
+                speed: filter.speed(),
+            };
+            handle.typemap().write().await.insert::<song::TrackOffset>(offset);
+        } else if !start.is_zero() {
+            handle.seek_async(start).await.ok();
+        }
 
-        (handle, title_future)
+        let crossfade = {
+            let mut guild_data = guild_data.lock().await;
+            guild_data.song_now = Some(data::song::Now::Playing{
+                track: handle.clone(),
+                request: request.clone(),
+                paused: false,
+            });
+            guild_data.song_config.crossfade.map(std::time::Duration::from_secs)
+        };
+
+        (handle, title_future, crossfade)
     };
 
-    if let Some(title) = title_future.await {
-        ctx.set_activity(Some(serenity::ActivityData::listening(title)))
-    } else {
-        ctx.set_activity(Some(serenity::ActivityData::listening("")))
-    }
+    let repeat = guild_data.lock().await.song_repeat;
+    let title = title_future.await.unwrap_or_default();
+    request.aux_metadata(ctx).await;
+    set_activity(ctx, repeat, title);
 
     handle.add_event(
         songbird::Event::Track(songbird::TrackEvent::End),
@@ -220,6 +533,50 @@ pub async fn play_internal(
             guild_id: request.guild_id,
         },
     )?;
+    handle.add_event(
+        songbird::Event::Periodic(player::UPDATE_INTERVAL, None),
+        player::UpdateHandler {
+            context: ctx.clone(),
+            guild_id: request.guild_id,
+        },
+    )?;
+    handle.add_event(
+        songbird::Event::Periodic(PREFETCH_CHECK_INTERVAL, None),
+        PrefetchHandler {
+            context: ctx.clone(),
+            guild_id: request.guild_id,
+        },
+    )?;
+    handle.add_event(
+        songbird::Event::Periodic(restore::SAVE_INTERVAL, None),
+        restore::SaveHandler {
+            context: ctx.clone(),
+            guild_id: request.guild_id,
+        },
+    )?;
+    if request.live_title.is_live() {
+        handle.add_event(
+            songbird::Event::Periodic(ICY_UPDATE_INTERVAL, None),
+            IcyHandler {
+                context: ctx.clone(),
+                request: request.clone(),
+                shown: std::sync::Mutex::new(None),
+            },
+        )?;
+    }
+    if let Some(crossfade) = crossfade {
+        handle.add_event(
+            songbird::Event::Periodic(CROSSFADE_CHECK_INTERVAL, None),
+            CrossfadeHandler {
+                context: ctx.clone(),
+                guild_id: request.guild_id,
+                crossfade,
+            },
+        )?;
+    }
+    player::update_nowait(ctx, request.guild_id);
+    restore::save_nowait(ctx, request.guild_id);
+    lyrics::start_nowait(ctx, request.clone(), handle.clone());
 
     request.set_state_nowait(ctx.clone(), song::RequestState::Playing);
 
@@ -230,123 +587,1393 @@ pub async fn queue_internal(
     ctx: &serenity::Context,
     request: std::sync::Arc<data::song::Request>,
 ) -> Result<SongCommandResult, Error> {
+    queue_many_internal(ctx, vec![request]).await
+}
 
-    let guild_id = request.guild_id;
+/// Lookups run at once to check the length of queued songs, each of which may start yt-dlp.
+const METADATA_LOOKUPS: usize = 4;
+
+pub async fn queue_many_internal(
+    ctx: &serenity::Context,
+    requests: Vec<std::sync::Arc<data::song::Request>>,
+) -> Result<SongCommandResult, Error> {
+    let guild_id = match requests.first() {
+        Some(request) => request.guild_id,
+        None => return Ok(SongCommandResult::Queue),
+    };
     let guild_data = data::Storage::guild(ctx, guild_id).await;
 
-    let first_queue = {
+    let max_duration = guild_data.lock().await.song_config.max_duration.map(std::time::Duration::from_secs);
+    let durations = match max_duration {
+        Some(_) => {
+            use futures::StreamExt;
+            let lookups: Vec<_> = requests.iter().map(|request| request.aux_metadata(ctx)).collect();
+            let metadata: Vec<_> = futures::stream::iter(lookups).buffered(METADATA_LOOKUPS).collect().await;
+            metadata
+                .into_iter()
+                .map(|metadata| metadata.and_then(|metadata| metadata.duration))
+                .collect()
+        }
+        None => vec![None; requests.len()],
+    };
+
+    let (queued, rejected, first_queue, skip_autoplay) = {
         let mut guild_data = guild_data.lock().await;
-        guild_data.song_queue.push_back(request.clone());
-        guild_data.song_queue.len() == 1 && guild_data.song_now.is_none()
+        let mut queued = Vec::with_capacity(requests.len());
+        let mut rejected = Vec::new();
+        for (request, duration) in requests.into_iter().zip(durations) {
+            match check_limits(&guild_data, &request, duration) {
+                Ok(()) => {
+                    guild_data.song_queue.push_back(request.clone());
+                    queued.push(request);
+                }
+                Err(err) => rejected.push((request, err)),
+            }
+        }
+
+        let first_queue = !queued.is_empty() && guild_data.song_queue.len() == queued.len() && guild_data.song_now.is_none();
+        let skip_autoplay = guild_data.song_now.as_ref().is_some_and(|now| now.request().autoplay)
+            && queued.iter().any(|request| !request.autoplay);
+        (queued, rejected, first_queue, skip_autoplay)
     };
 
-    request.set_state_nowait(ctx.clone(), song::RequestState::Queue);
+    // reactions added while the requests were expanded or looked up are missed by the events, so they are fetched once here
+    let mut message_ids = std::collections::HashSet::new();
+    for request in queued.iter() {
+        if !message_ids.insert(request.message_id) {
+            continue;
+        }
+        if let Ok(voters) = request.fetch_voters(ctx).await {
+            for queued_request in queued.iter().filter(|queued_request| queued_request.message_id == request.message_id) {
+                queued_request.voters.lock().unwrap().extend(voters.iter().copied());
+            }
+        }
+    }
+
+    for request in queued.iter() {
+        request.clone().set_state_nowait(ctx.clone(), song::RequestState::Queue);
+    }
+    for (request, _) in rejected.iter() {
+        request.clone().set_state_nowait(ctx.clone(), song::RequestState::Canceled);
+    }
+
+    let num_rejected = rejected.len();
+    if let Some((request, err)) = rejected.into_iter().next() {
+        if queued.is_empty() {
+            return Err(err.into());
+        }
+        if let Some(message) = request.messge(ctx).await {
+            message.reply(ctx, format!("{} songs not queued : {}", num_rejected, err)).await?;
+        }
+    }
 
-    if first_queue {
+    if first_queue || skip_autoplay {
         next_internal(ctx, guild_id).await?;
         Ok(SongCommandResult::Play)
     } else {
+        player::update_nowait(ctx, guild_id);
+        restore::save_nowait(ctx, guild_id);
         Ok(SongCommandResult::Queue)
     }
 }
 
+/// Checks the guild's queue limits for adding `request`, whose length is `duration`.
+fn check_limits(
+    guild_data: &data::Guild,
+    request: &data::song::Request,
+    duration: Option<std::time::Duration>,
+) -> Result<(), SongError> {
+    let config = &guild_data.song_config;
+
+    if let Some(max) = config.max_queue {
+        if guild_data.song_queue.len() >= max {
+            return Err(SongError::QueueLimit(max));
+        }
+    }
+
+    if let Some(max) = config.max_user_queue {
+        let pending = guild_data
+            .song_queue
+            .iter()
+            .filter(|queued| queued.author_id == request.author_id)
+            .count();
+        if pending >= max {
+            return Err(SongError::UserQueueLimit(max));
+        }
+    }
+
+    if let Some(max) = config.max_duration.map(std::time::Duration::from_secs) {
+        match duration {
+            Some(duration) if duration > max => return Err(SongError::DurationLimit(max)),
+            Some(_) => {}
+            // live streams and songs whose metadata could not be looked up would get past the limit
+            None => return Err(SongError::UnknownDuration(max)),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn request_internal(
+    ctx: &serenity::Context,
+    request: data::song::Request,
+) -> Result<SongCommandResult, Error> {
+    if !request.source.is_playlist(ctx).await {
+        return queue_internal(ctx, Arc::new(request)).await;
+    }
+
+    let message = request.messge(ctx).await.ok_or(Error::from("Cannot find request message."))?;
+    let mut reply = message.reply(ctx, "loading playlist...").await?;
+
+    let limit = data::Storage::guild(ctx, request.guild_id)
+        .await
+        .lock()
+        .await
+        .song_config
+        .playlist_limit;
+
+    let playlist = match request.source.expand(ctx, limit, Some(&mut reply)).await? {
+        Some(playlist) => playlist,
+        None => return queue_internal(ctx, Arc::new(request)).await,
+    };
+
+    let num_sources = playlist.sources.len();
+    if num_sources == 0 {
+        // reported in the reply, like the other results of the playlist
+        reply.edit(ctx, serenity::EditMessage::new().content(format!("{} : no playable songs", playlist.title))).await?;
+        return Ok(SongCommandResult::Queue);
+    }
+
+    let summary = if num_sources < playlist.total {
+        format!("{} : queued {} of {} songs", playlist.title, num_sources, playlist.total)
+    } else {
+        format!("{} : queued {} songs", playlist.title, num_sources)
+    };
+    reply.edit(ctx, serenity::EditMessage::new().content(summary)).await?;
+
+    let group = song::RequestGroup::new();
+    let requests = playlist
+        .sources
+        .into_iter()
+        .map(|source| Arc::new(request.with_source(source).in_group(&group)))
+        .collect();
+    queue_many_internal(ctx, requests).await
+}
+
 pub async fn cancel_internal(
     ctx: &serenity::Context,
     guild_id: serenity::GuildId,
 ) -> Result<(), Error> {
-    let guild_data = data::Storage::guild(ctx, guild_id).await;
-    guild_data.lock().await.song_now_cancel(ctx);
+    let track = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        let track = guild_data.song_now.as_ref().and_then(|now| now.track());
+        if guild_data.song_now.is_some() {
+            guild_data.song_now_cancel(ctx);
+            guild_data.save().await?;
+        }
+        track
+    };
 
-    if let Some(call) = get_internal(ctx, guild_id).await {
-        let mut call = call.lock().await;
-        call.stop();
+    match track {
+        Some(track) => fade_out_nowait(track, FADE_DURATION),
+        // nothing is playing yet, but tracks being replaced may still be fading out
+        None => {
+            if let Some(call) = get_internal(ctx, guild_id).await {
+                call.lock().await.stop();
+            }
+        }
     }
 
     ctx.set_activity(None);
+    player::update_nowait(ctx, guild_id);
+    restore::save_nowait(ctx, guild_id);
 
     Ok(())
 }
 
-pub async fn next_internal(
+pub async fn stop_internal(
     ctx: &serenity::Context,
     guild_id: serenity::GuildId,
 ) -> Result<(), Error> {
     cancel_internal(ctx, guild_id).await?;
+
     let guild_data = data::Storage::guild(ctx, guild_id).await;
+    guild_data.lock().await.song_queue_clear(ctx);
+    player::update_nowait(ctx, guild_id);
+    restore::save_nowait(ctx, guild_id);
+    Ok(())
+}
 
-    loop
+pub async fn pause_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    paused: bool,
+) -> Result<(), Error> {
     {
-        let next = guild_data.lock().await.song_queue_take(ctx).await;
-
-        match next {
-            Some(next) => {
-                match play_internal(ctx, next.clone()).await {
-                    Ok(_) => return Ok(()),
-                    Err(e) => {
-                        if let Ok(message) = ctx.http.get_message(next.channel_id, next.message_id).await {
-                            let error_message: String = format!("error : {:?}", e);
-                            message.reply(ctx, error_message).await?;
-                        }
-                        next.set_state_nowait(ctx.clone(), song::RequestState::Canceled);
-                    },
-                }
-            }
-            None => {
-                return Ok(());
-            }
-        }
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        let now = guild_data.song_now.as_mut().ok_or(SongError::NotPlaying)?;
+        now.set_paused(paused)?;
     }
-}
 
-#[poise::command(
-    slash_command,
-    guild_only,
-    subcommands("join", "leave", "stop", "next"),
-    subcommand_required
-)]
-pub async fn song(_: Context<'_>) -> Result<(), Error> {
+    player::update_nowait(ctx, guild_id);
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn join(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.reply("join").await?;
-    join_internal(
-        ctx.serenity_context(),
-        ctx.guild_id().unwrap(),
-        ctx.author().id,
-    )
-    .await?;
+pub async fn repeat_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    mode: song::RepeatMode,
+) -> Result<(), Error> {
+    {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_repeat = mode;
+        guild_data.save().await?;
+    }
+
+    player::update_nowait(ctx, guild_id);
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+/// Plays the current song again from `position`, to apply the changed filter.
+pub async fn restart_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    position: std::time::Duration,
+) -> Result<(), Error> {
+    let request = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let guild_data = guild_data.lock().await;
+        guild_data.song_now.as_ref().map(|now| now.request()).ok_or(SongError::NotPlaying)?
+    };
+
+    if play_from_internal(ctx, request, position, FADE_DURATION).await.is_err() {
+        next_internal(ctx, guild_id).await?;
+    }
+    Ok(())
+}
+
+pub async fn next_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> Result<(), Error> {
+    skip_to_internal(ctx, guild_id, None).await
+}
+
+/// Stops the current song and plays `request`, falling back to the queue if it is `None` or fails.
+pub async fn skip_to_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    request: Option<Arc<data::song::Request>>,
+) -> Result<(), Error> {
+    cancel_internal(ctx, guild_id).await?;
+    play_next_internal(ctx, guild_id, request, FADE_DURATION).await
+}
+
+/// Plays `request` fading in over `fade_in`, falling back to the queue if it is `None` or fails.
+async fn play_next_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    request: Option<Arc<data::song::Request>>,
+    fade_in: std::time::Duration,
+) -> Result<(), Error> {
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+
+    let mut next = request;
+    let mut autoplayed = false;
+    loop
+    {
+        if next.is_none() {
+            next = guild_data.lock().await.song_queue_take();
+        }
+        if next.is_none() && !autoplayed {
+            autoplayed = true;
+            next = autoplay_internal(ctx, guild_id).await;
+        }
+
+        match next.take() {
+            Some(next) => {
+                match play_from_internal(ctx, next.clone(), std::time::Duration::ZERO, fade_in).await {
+                    Ok(_) => return Ok(()),
+                    Err(e) => {
+                        if let Ok(message) = ctx.http.get_message(next.channel_id, next.message_id).await {
+                            let error_message: String = format!("error : {:?}", e);
+                            message.reply(ctx, error_message).await?;
+                        }
+                        next.set_state_nowait(ctx.clone(), song::RequestState::Canceled);
+                    },
+                }
+            }
+            None => {
+                ctx.set_activity(None);
+                player::update_nowait(ctx, guild_id);
+                restore::save_nowait(ctx, guild_id);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Picks a request related to the recent songs, if autoplay is enabled.
+pub async fn autoplay_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> Option<Arc<data::song::Request>> {
+    const NUM_HISTORY: usize = 10;
+
+    let (channel_id, history) = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let guild_data = guild_data.lock().await;
+        if !guild_data.song_config.autoplay {
+            return None;
+        }
+        let history: Vec<_> = guild_data.song_history.iter().rev().take(NUM_HISTORY).cloned().collect();
+        (guild_data.channel_song?, history)
+    };
+
+    let source = song::autoplay_source(ctx, &history).await?;
+    let message = channel_id.say(ctx, format!("autoplay : {}", source)).await.ok()?;
+
+    Some(Arc::new(
+        data::song::Request::new(
+            source,
+            guild_id,
+            ctx.cache.current_user().id,
+            channel_id,
+            message.id,
+            None::<String>,
+        )
+        .autoplay(),
+    ))
+}
+
+pub enum SkipResult {
+    Skipped,
+    Voted { votes: usize, required: usize },
+}
+
+/// Non-bot users in the voice channel the bot is connected to.
+fn voice_listeners(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> std::collections::HashSet<serenity::UserId> {
+    let bot_id = ctx.cache.current_user().id;
+    let guild = match ctx.cache.guild(guild_id) {
+        Some(guild) => guild,
+        None => return Default::default(),
+    };
+    let channel_id = match guild.voice_states.get(&bot_id).and_then(|state| state.channel_id) {
+        Some(channel_id) => channel_id,
+        None => return Default::default(),
+    };
+
+    guild
+        .voice_states
+        .values()
+        .filter(|state| state.channel_id == Some(channel_id))
+        .filter(|state| {
+            state
+                .member
+                .as_ref()
+                .or_else(|| guild.members.get(&state.user_id))
+                .is_some_and(|member| !member.user.bot)
+        })
+        .map(|state| state.user_id)
+        .collect()
+}
+
+/// Skips the current song, or counts a vote for it if vote-skip is enabled, see `song::Config::skip_mode`.
+pub async fn skip_vote_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    user_id: serenity::UserId,
+) -> Result<SkipResult, Error> {
+    let result = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        let now = guild_data.song_now.as_ref().ok_or(SongError::NotPlaying)?.request();
+        let is_dj = is_dj(ctx, &guild_data, user_id);
+
+        match guild_data.song_config.skip_mode(now.author_id == user_id, is_dj) {
+            None => return Err(SongError::Permission.into()),
+            Some(song::SkipMode::Vote(percent)) => {
+                let listeners = voice_listeners(ctx, guild_id);
+                if !listeners.contains(&user_id) {
+                    return Err(SongError::NotListening.into());
+                }
+
+                // votes of listeners who have left no longer count
+                guild_data.song_skip_votes.insert(user_id);
+                guild_data.song_skip_votes.retain(|user_id| listeners.contains(user_id));
+
+                let votes = guild_data.song_skip_votes.len();
+                let required = (listeners.len() * percent as usize).div_ceil(100).max(1);
+                if votes >= required {
+                    SkipResult::Skipped
+                } else {
+                    SkipResult::Voted { votes, required }
+                }
+            }
+            Some(song::SkipMode::Now) => SkipResult::Skipped,
+        }
+    };
+
+    match result {
+        SkipResult::Skipped => next_internal(ctx, guild_id).await?,
+        SkipResult::Voted { .. } => player::update_nowait(ctx, guild_id),
+    }
+    Ok(result)
+}
+
+/// Withdraws a skip vote for the current song.
+pub async fn unvote_skip_internal(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    user_id: serenity::UserId,
+) {
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+    if guild_data.lock().await.song_skip_votes.remove(&user_id) {
+        player::update_nowait(ctx, guild_id);
+    }
+}
+
+/// DJs have the guild's DJ role, or can manage channels.
+fn is_dj(ctx: &serenity::Context, guild_data: &data::Guild, user_id: serenity::UserId) -> bool {
+    let guild = match ctx.cache.guild(guild_data.id) {
+        Some(guild) => guild,
+        None => return false,
+    };
+    let member = match guild.members.get(&user_id) {
+        Some(member) => member,
+        None => return false,
+    };
+
+    let has_role = guild_data
+        .song_config
+        .dj_role
+        .is_some_and(|role_id| member.roles.contains(&role_id));
+    has_role || guild.member_permissions(member).manage_channels()
+}
+
+/// Whether `user_id` may perform `action`, which affects `requests`.
+pub fn is_permitted(
+    ctx: &serenity::Context,
+    guild_data: &data::Guild,
+    user_id: serenity::UserId,
+    action: song::Action,
+    requests: &[Arc<data::song::Request>],
+) -> bool {
+    let is_requester = requests.iter().all(|request| request.author_id == user_id);
+    guild_data.song_config.permission(action).allows(is_requester, is_dj(ctx, guild_data, user_id))
+}
+
+/// Songs affected by `action`, or `None` if it depends on the arguments and the command checks it.
+fn affected_requests(guild_data: &data::Guild, action: song::Action) -> Option<Vec<Arc<data::song::Request>>> {
+    let now = guild_data.song_now.as_ref().map(|now| now.request());
+    let queue = guild_data.song_queue.iter().cloned();
+    match action {
+        song::Action::Join
+        | song::Action::Next
+        | song::Action::Pause
+        | song::Action::Seek
+        | song::Action::Volume
+        | song::Action::Repeat
+        | song::Action::Filter => Some(now.into_iter().collect()),
+        song::Action::Leave | song::Action::Stop => Some(now.into_iter().chain(queue).collect()),
+        song::Action::Shuffle | song::Action::Move => Some(queue.collect()),
+        song::Action::Autoplay => Some(Vec::new()),
+        song::Action::Remove | song::Action::SkipTo => None,
+    }
+}
+
+pub async fn check_permission(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    user_id: serenity::UserId,
+    action: song::Action,
+) -> Result<(), Error> {
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+    let guild_data = guild_data.lock().await;
+    match affected_requests(&guild_data, action) {
+        Some(requests) if !is_permitted(ctx, &guild_data, user_id, action, &requests) => {
+            Err(SongError::Permission.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Shared by all song commands, see `song::Action::from_command`.
+async fn check(ctx: Context<'_>) -> Result<bool, Error> {
+    let (guild_id, action) = match (ctx.guild_id(), song::Action::from_command(&ctx.command().name)) {
+        (Some(guild_id), Some(action)) => (guild_id, action),
+        _ => return Ok(true),
+    };
+
+    match check_permission(ctx.serenity_context(), guild_id, ctx.author().id, action).await {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            ctx.send(poise::CreateReply::default().content(err.to_string()).ephemeral(true)).await?;
+            Ok(false)
+        }
+    }
+}
+
+fn queue_index(position: usize, len: usize) -> Result<usize, Error> {
+    if position == 0 || position > len {
+        return Err(SongError::QueuePosition.into());
+    }
+    Ok(position - 1)
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "check",
+    subcommands(
+        "join", "leave", "play", "search", "queue", "remove", "move_", "skipto", "repeat", "shuffle",
+        "pause", "resume", "seek", "volume", "history", "replay", "autoplay", "lyrics_", "filter", "stop", "next",
+        "config::config", "library::library"
+    ),
+    subcommand_required
+)]
+pub async fn song(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn join(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.reply("join").await?;
+    join_internal(
+        ctx.serenity_context(),
+        ctx.guild_id().unwrap(),
+        ctx.author().id,
+    )
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
     if let Some(call) = get_internal(ctx.serenity_context(), ctx.guild_id().unwrap()).await {
         {
             let mut call = call.lock().await;
             call.leave().await?;
         }
+        restore::save_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
         ctx.reply("leave").await?;
     }
     Ok(())
 }
 
+const AUTOCOMPLETE_MIN_LEN: usize = 3;
+const AUTOCOMPLETE_RESULTS: usize = 5;
+/// Discord drops autocompletions answered later than 3 seconds.
+const AUTOCOMPLETE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(2500);
+const AUTOCOMPLETE_CACHE_SIZE: usize = 256;
+const AUTOCOMPLETE_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
+lazy_static::lazy_static! {
+    /// Recent autocompletions by query, since they are asked again on every keystroke.
+    static ref autocomplete_cache: std::sync::Mutex<std::collections::HashMap<String, (std::time::Instant, Vec<serenity::AutocompleteChoice>)>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+async fn autocomplete_play(_ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let query = partial.trim().to_lowercase();
+    if query.chars().count() < AUTOCOMPLETE_MIN_LEN || song::provider::domain(&query).is_some() {
+        return Vec::new();
+    }
+
+    if let Some((time, choices)) = autocomplete_cache.lock().unwrap().get(&query) {
+        if time.elapsed() < AUTOCOMPLETE_CACHE_TTL {
+            return choices.clone();
+        }
+    }
+
+    let results = match tokio::time::timeout(AUTOCOMPLETE_TIMEOUT, song::youtube_search(&query, AUTOCOMPLETE_RESULTS)).await {
+        Ok(Ok(results)) => results,
+        _ => return Vec::new(),
+    };
+    let choices: Vec<_> = results
+        .into_iter()
+        .filter_map(|metadata| {
+            let url = metadata.source_url?;
+            let title = metadata.title.unwrap_or_else(|| url.clone());
+            let name = match metadata.channel {
+                Some(channel) => format!("{} - {}", title, channel),
+                None => title,
+            };
+            Some(serenity::AutocompleteChoice::new(name.chars().take(100).collect::<String>(), url))
+        })
+        .collect();
+
+    let mut cache = autocomplete_cache.lock().unwrap();
+    cache.retain(|_, (time, _)| time.elapsed() < AUTOCOMPLETE_CACHE_TTL);
+    if cache.len() >= AUTOCOMPLETE_CACHE_SIZE {
+        let oldest = cache.iter().min_by_key(|(_, (time, _))| *time).map(|(query, _)| query.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(query, (std::time::Instant::now(), choices.clone()));
+    choices
+}
+
+/// 노래를 검색하거나 링크로 재생 목록에 추가합니다.
 #[poise::command(slash_command)]
-pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.reply("song stop").await?;
-    cancel_internal
-(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "검색어 또는 링크"]
+    #[autocomplete = "autocomplete_play"]
+    query: String,
+) -> Result<(), Error> {
+    let reply = ctx.reply(&query).await?;
+    let message = reply.message().await?;
+
+    let request = data::song::Request::new(
+        data::song::Source::Chat(query),
+        ctx.guild_id().expect("This command can only be used within guilds."),
+        ctx.author().id,
+        ctx.channel_id(),
+        message.id,
+        ctx.locale(),
+    );
+
+    request_internal(ctx.serenity_context(), request).await?;
+    Ok(())
+}
+
+const SEARCH_RESULTS: usize = 10;
+const SEARCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 노래를 검색해서 결과 중에 골라 재생 목록에 추가합니다.
+#[poise::command(slash_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "검색어"]
+    query: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let results: Vec<_> = song::youtube_search(&query, SEARCH_RESULTS)
+        .await?
+        .into_iter()
+        .filter(|metadata| metadata.source_url.is_some())
+        .collect();
+    if results.is_empty() {
+        ctx.reply(format!("🔎 {} : no results", query)).await?;
+        return Ok(());
+    }
+
+    let options = results
+        .iter()
+        .enumerate()
+        .map(|(index, metadata)| {
+            let title = metadata.title.clone().unwrap_or_else(|| query.clone());
+            let duration = metadata
+                .duration
+                .map(song::format_duration)
+                .unwrap_or_else(|| String::from("?:??"));
+            let description = match &metadata.channel {
+                Some(channel) => format!("{} · {}", channel, duration),
+                None => duration,
+            };
+            serenity::CreateSelectMenuOption::new(title.chars().take(100).collect::<String>(), index.to_string())
+                .description(description.chars().take(100).collect::<String>())
+        })
+        .collect();
+
+    let menu_id = format!("{}search", ctx.id());
+    let menu = serenity::CreateSelectMenu::new(&menu_id, serenity::CreateSelectMenuKind::String { options })
+        .placeholder("pick a song");
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(format!("🔎 {}", query))
+                .components(vec![serenity::CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let author_id = ctx.author().id;
+    let deadline = tokio::time::Instant::now() + SEARCH_TIMEOUT;
+    let picked = loop {
+        let menu_id = menu_id.clone();
+        let pick = match serenity::collector::ComponentInteractionCollector::new(ctx)
+            .filter(move |pick| pick.data.custom_id == menu_id)
+            .timeout(deadline.saturating_duration_since(tokio::time::Instant::now()))
+            .await
+        {
+            Some(pick) => pick,
+            None => break None,
+        };
+
+        // every click is answered, or discord shows it as failed
+        if pick.user.id != author_id {
+            pick.create_response(
+                ctx,
+                serenity::CreateInteractionResponse::Message(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content("only the one who searched can pick a song.")
+                        .ephemeral(true),
+                ),
+            )
+            .await
+            .ok();
+            continue;
+        }
+
+        let metadata = match &pick.data.kind {
+            serenity::ComponentInteractionDataKind::StringSelect { values } => {
+                values.first().and_then(|value| value.parse::<usize>().ok()).and_then(|index| results.get(index))
+            }
+            _ => None,
+        };
+        break Some((pick, metadata));
+    };
+    let (pick, metadata) = match picked {
+        Some((pick, Some(metadata))) => (pick, metadata),
+        Some((pick, None)) => {
+            pick.create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(format!("🔎 {} : no song picked", query))
+                        .components(vec![]),
+                ),
+            )
+            .await
+            .ok();
+            return Ok(());
+        }
+        None => {
+            reply
+                .edit(ctx, poise::CreateReply::default().content(format!("🔎 {} : timed out", query)).components(vec![]))
+                .await
+                .ok();
+            return Ok(());
+        }
+    };
+
+    let url = metadata.source_url.clone().unwrap();
+    let title = metadata.title.clone().unwrap_or_else(|| url.clone());
+    pick.create_response(
+        ctx,
+        serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .content(format!("🔎 [{}]({})", title, url))
+                .components(vec![]),
+        ),
+    )
+    .await?;
 
+    let message = reply.message().await?;
+    let request = data::song::Request::new(
+        data::song::Source::Chat(url),
+        ctx.guild_id().expect("This command can only be used within guilds."),
+        ctx.author().id,
+        ctx.channel_id(),
+        message.id,
+        ctx.locale(),
+    );
+
+    queue_internal(ctx.serenity_context(), Arc::new(request)).await?;
+    Ok(())
+}
+
+const QUEUE_PAGE_SIZE: usize = 10;
+
+async fn queue_entry_line(ctx: &serenity::Context, request: &data::song::Request) -> String {
+    let metadata = request.aux_metadata(ctx).await.unwrap_or_default();
+
+    let title = metadata.title.unwrap_or_else(|| request.source.to_string());
+    let title = match metadata.source_url {
+        Some(url) => format!("[{}]({})", title, url),
+        None => title,
+    };
+    let duration = metadata
+        .duration
+        .map(song::format_duration)
+        .unwrap_or_else(|| String::from("?:??"));
+
+    format!("{} · {} · <@{}>", title, duration, request.author_id)
+}
+
+struct QueueSnapshot {
+    now: Option<(Arc<data::song::Request>, Option<std::time::Duration>, bool)>,
+    repeat: song::RepeatMode,
+    scheduler: song::Scheduler,
+    queue: Vec<Arc<data::song::Request>>,
+    priorities: Vec<usize>,
+    next: Option<usize>,
+}
+
+impl QueueSnapshot {
+    async fn new(ctx: &serenity::Context, guild_id: serenity::GuildId) -> Self {
+        let (now, repeat, scheduler, recent_authors, queue) = {
+            let guild_data = data::Storage::guild(ctx, guild_id).await;
+            let guild_data = guild_data.lock().await;
+            let now = guild_data.song_now.as_ref().map(|now| (now.request(), now.track(), now.is_paused()));
+            (
+                now,
+                guild_data.song_repeat,
+                guild_data.song_scheduler,
+                guild_data.song_recent_authors(),
+                guild_data.song_queue.iter().cloned().collect::<Vec<_>>(),
+            )
+        };
+
+        let now = match now {
+            Some((request, Some(track), paused)) => Some((request, song::track_position(&track).await, paused)),
+            Some((request, None, paused)) => Some((request, None, paused)),
+            None => None,
+        };
+
+        let priorities = queue.iter().map(|request| request.priority()).collect();
+        let next = scheduler.select(&queue, &recent_authors);
+
+        Self {
+            now,
+            repeat,
+            scheduler,
+            queue,
+            priorities,
+            next,
+        }
+    }
+
+    fn num_pages(&self) -> usize {
+        self.queue.len().div_ceil(QUEUE_PAGE_SIZE).max(1)
+    }
+
+    async fn embed(&self, ctx: &serenity::Context, page: usize) -> serenity::CreateEmbed {
+        let mut description = String::new();
+
+        match &self.now {
+            Some((request, position, paused)) => {
+                match self.repeat.emoji() {
+                    Some(emoji) => description.push_str(&format!("**Now Playing** {} {}\n", emoji, self.repeat)),
+                    None => description.push_str("**Now Playing**\n"),
+                }
+                description.push_str(&queue_entry_line(ctx, request).await);
+                if let Some(position) = position {
+                    description.push_str(&format!(" · {} elapsed", song::format_duration(*position)));
+                }
+                if *paused {
+                    description.push_str(" · ⏸ paused");
+                }
+                description.push_str("\n\n");
+            }
+            None => description.push_str("**Nothing is playing**\n\n"),
+        }
+
+        if self.queue.is_empty() {
+            description.push_str("Queue is empty");
+        } else {
+            description.push_str("**Queue**\n");
+            let start = page * QUEUE_PAGE_SIZE;
+            let end = (start + QUEUE_PAGE_SIZE).min(self.queue.len());
+            let lines = futures::future::join_all(
+                self.queue[start..end].iter().map(|request| queue_entry_line(ctx, request)),
+            )
+            .await;
+            for (index, line) in (start..end).zip(lines) {
+                let marker = if self.next == Some(index) { "▶ " } else { "" };
+                description.push_str(&format!("{}`{}.` {}", marker, index + 1, line));
+                if self.scheduler == song::Scheduler::Priority {
+                    let emoji = song::RequestState::Queue.emoji().unwrap();
+                    description.push_str(&format!(" · {} {}", emoji, self.priorities[index]));
+                }
+                description.push('\n');
+            }
+        }
+
+        serenity::CreateEmbed::new()
+            .title("Song Queue")
+            .description(description)
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "page {}/{} · {} songs queued · {}",
+                page + 1,
+                self.num_pages(),
+                self.queue.len(),
+                match self.scheduler {
+                    song::Scheduler::Priority => format!("the most {} plays next", song::RequestState::Queue.emoji().unwrap()),
+                    song::Scheduler::Fifo => String::from("first come, first played"),
+                    song::Scheduler::RoundRobin => String::from("takes turns between requesters"),
+                },
+            )))
+    }
+}
+
+/// Sends the first page, and turns the pages with buttons until they are left alone for a while.
+async fn paginate<F, Fut>(ctx: Context<'_>, num_pages: usize, embed: F) -> Result<(), Error>
+where
+    F: Fn(usize) -> Fut,
+    Fut: futures::Future<Output = serenity::CreateEmbed>,
+{
+    let serenity_ctx = ctx.serenity_context();
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{}prev", ctx_id);
+    let next_button_id = format!("{}next", ctx_id);
+    let components = |page: usize| {
+        vec![serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(&prev_button_id)
+                .emoji('◀')
+                .disabled(page == 0),
+            serenity::CreateButton::new(&next_button_id)
+                .emoji('▶')
+                .disabled(page + 1 >= num_pages),
+        ])]
+    };
+
+    let mut page = 0;
+    let mut reply_builder = poise::CreateReply::default().embed(embed(page).await);
+    if num_pages > 1 {
+        reply_builder = reply_builder.components(components(page));
+    }
+    let reply = ctx.send(reply_builder).await?;
+
+    if num_pages <= 1 {
+        return Ok(());
+    }
+
+    while let Some(press) = serenity::collector::ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(std::time::Duration::from_secs(600))
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            page = (page + 1).min(num_pages - 1);
+        } else if press.data.custom_id == prev_button_id {
+            page = page.saturating_sub(1);
+        } else {
+            continue;
+        }
+
+        press.defer(serenity_ctx).await?;
+        reply
+            .edit(
+                ctx,
+                poise::CreateReply::default()
+                    .embed(embed(page).await)
+                    .components(components(page)),
+            )
+            .await?;
+    }
+
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .embed(embed(page).await)
+                .components(vec![]),
+        )
+        .await
+        .ok();
+
+    Ok(())
+}
+
+/// 재생 중인 노래와 대기열을 표시합니다.
+#[poise::command(slash_command)]
+pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let serenity_ctx = ctx.serenity_context();
+    let snapshot = QueueSnapshot::new(serenity_ctx, ctx.guild_id().unwrap()).await;
+
+    paginate(ctx, snapshot.num_pages(), |page| snapshot.embed(serenity_ctx, page)).await
+}
+
+/// 대기열에서 노래를 제거합니다.
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "대기열 번호"]
+    #[min = 1]
+    position: usize,
+) -> Result<(), Error> {
+    let request = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
+        let mut guild_data = guild_data.lock().await;
+        let index = queue_index(position, guild_data.song_queue.len())?;
+        let requests = [guild_data.song_queue[index].clone()];
+        if !is_permitted(ctx.serenity_context(), &guild_data, ctx.author().id, song::Action::Remove, &requests) {
+            return Err(SongError::Permission.into());
+        }
+        guild_data.song_queue.remove(index).unwrap()
+    };
+
+    request.clone().set_state_nowait(ctx.serenity_context().clone(), song::RequestState::Canceled);
+    player::update_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+    restore::save_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+    ctx.reply(format!("removed {} : {}", position, request.source)).await?;
+    Ok(())
+}
+
+/// 대기열에서 노래의 순서를 옮깁니다.
+#[poise::command(slash_command, rename = "move")]
+pub async fn move_(
+    ctx: Context<'_>,
+    #[description = "옮길 노래의 대기열 번호"]
+    #[min = 1]
+    from: usize,
+    #[description = "새 대기열 번호"]
+    #[min = 1]
+    to: usize,
+) -> Result<(), Error> {
+    let request = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
+        let mut guild_data = guild_data.lock().await;
+        let len = guild_data.song_queue.len();
+        let from_index = queue_index(from, len)?;
+        let to_index = queue_index(to, len)?;
+        let request = guild_data.song_queue.remove(from_index).unwrap();
+        guild_data.song_queue.insert(to_index, request.clone());
+        request
+    };
+
+    player::update_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+    restore::save_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+    ctx.reply(format!("moved {} -> {} : {}", from, to, request.source)).await?;
+    Ok(())
+}
+
+/// 대기열의 노래로 바로 건너뜁니다. 사이의 노래는 건너뛴 것으로 처리됩니다.
+#[poise::command(slash_command)]
+pub async fn skipto(
+    ctx: Context<'_>,
+    #[description = "대기열 번호"]
+    #[min = 1]
+    position: usize,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let request = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        let index = queue_index(position, guild_data.song_queue.len())?;
+
+        let requests: Vec<_> = guild_data
+            .song_now
+            .as_ref()
+            .map(|now| now.request())
+            .into_iter()
+            .chain(guild_data.song_queue.iter().take(index).cloned())
+            .collect();
+        if !is_permitted(ctx.serenity_context(), &guild_data, ctx.author().id, song::Action::SkipTo, &requests) {
+            return Err(SongError::Permission.into());
+        }
+
+        guild_data.song_queue_skip_to(ctx.serenity_context(), index).unwrap()
+    };
+
+    player::update_nowait(ctx.serenity_context(), guild_id);
+    restore::save_nowait(ctx.serenity_context(), guild_id);
+    ctx.reply(format!("skip to {} : {}", position, request.source)).await?;
+    skip_to_internal(ctx.serenity_context(), guild_id, Some(request)).await?;
+    Ok(())
+}
+
+/// 반복 재생 모드를 설정합니다.
+#[poise::command(slash_command)]
+pub async fn repeat(ctx: Context<'_>, mode: song::RepeatMode) -> Result<(), Error> {
+    repeat_internal(ctx.serenity_context(), ctx.guild_id().unwrap(), mode).await?;
+
+    ctx.reply(format!("repeat : {}", mode)).await?;
+    Ok(())
+}
+
+/// 대기열을 섞습니다.
+#[poise::command(slash_command)]
+pub async fn shuffle(ctx: Context<'_>) -> Result<(), Error> {
     let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
-    guild_data.lock().await.song_queue_clear(ctx.serenity_context());
+    guild_data.lock().await.song_queue_shuffle();
+    player::update_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+    restore::save_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+
+    ctx.reply("song shuffle").await?;
+    Ok(())
+}
+
+/// 재생 중인 노래를 일시 정지합니다.
+#[poise::command(slash_command)]
+pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
+    pause_internal(ctx.serenity_context(), ctx.guild_id().unwrap(), true).await?;
+
+    ctx.reply("song pause").await?;
     Ok(())
 }
 
+/// 일시 정지한 노래를 다시 재생합니다.
+#[poise::command(slash_command)]
+pub async fn resume(ctx: Context<'_>) -> Result<(), Error> {
+    pause_internal(ctx.serenity_context(), ctx.guild_id().unwrap(), false).await?;
+
+    ctx.reply("song resume").await?;
+    Ok(())
+}
+
+/// 재생 중인 노래의 재생 위치를 옮깁니다.
+#[poise::command(slash_command)]
+pub async fn seek(
+    ctx: Context<'_>,
+    #[description = "재생 위치 (형식 : 1:23)"]
+    position: String,
+) -> Result<(), Error> {
+    let position = song::parse_duration(&position).ok_or(Error::from("Invalid position. Use mm:ss."))?;
+
+    let track = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
+        let guild_data = guild_data.lock().await;
+        guild_data.song_now.as_ref().and_then(|now| now.track()).ok_or(SongError::NotPlaying)?
+    };
+
+    ctx.defer().await?;
+    let position = match song::TrackOffset::of(&track).await {
+        // filtered tracks are streamed by ffmpeg, so they restart from the position instead
+        Some(_) => {
+            restart_internal(ctx.serenity_context(), ctx.guild_id().unwrap(), position).await?;
+            position
+        }
+        None => track.seek_async(position).await?,
+    };
+    ctx.reply(format!("song seek {}", song::format_duration(position))).await?;
+    Ok(())
+}
+
+/// 음량을 설정합니다. 설정한 음량은 이후의 노래에도 적용됩니다.
+#[poise::command(slash_command)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "음량 (%)"]
+    #[min = 0]
+    #[max = 200]
+    volume: u32,
+) -> Result<(), Error> {
+    {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
+        let mut guild_data = guild_data.lock().await;
+        if let Some(track) = guild_data.song_now.as_ref().and_then(|now| now.track()) {
+            track.set_volume(volume as f32 / 100.0)?;
+        }
+        guild_data.song_config.volume = volume;
+        guild_data.save().await?;
+    }
+    player::update_nowait(ctx.serenity_context(), ctx.guild_id().unwrap());
+
+    ctx.reply(format!("song volume {}%", volume)).await?;
+    Ok(())
+}
+
+const HISTORY_PAGE_SIZE: usize = 10;
+
+/// 최근에 재생한 노래 목록을 표시합니다.
+#[poise::command(slash_command)]
+pub async fn history(ctx: Context<'_>) -> Result<(), Error> {
+    let lines: Vec<String> = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), ctx.guild_id().unwrap()).await;
+        let guild_data = guild_data.lock().await;
+        guild_data
+            .song_history
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, entry)| {
+                let title = match &entry.url {
+                    Some(url) => format!("[{}]({})", entry.title, url),
+                    None => entry.title.clone(),
+                };
+                format!(
+                    "`{}.` {} {} · <@{}> · <t:{}:R>",
+                    index + 1,
+                    entry.state.emoji().unwrap_or(' '),
+                    title,
+                    entry.author_id,
+                    entry.time.timestamp()
+                )
+            })
+            .collect()
+    };
+
+    if lines.is_empty() {
+        ctx.reply("no song history").await?;
+        return Ok(());
+    }
+
+    let num_pages = lines.len().div_ceil(HISTORY_PAGE_SIZE);
+    paginate(ctx, num_pages, |page| {
+        let start = page * HISTORY_PAGE_SIZE;
+        let end = (start + HISTORY_PAGE_SIZE).min(lines.len());
+        let embed = serenity::CreateEmbed::new()
+            .title("Song History")
+            .description(lines[start..end].join("\n"))
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "page {}/{} · {} songs played",
+                page + 1,
+                num_pages,
+                lines.len()
+            )));
+        futures::future::ready(embed)
+    })
+    .await
+}
+
+/// 최근에 재생한 노래를 다시 재생 목록에 추가합니다.
+#[poise::command(slash_command)]
+pub async fn replay(
+    ctx: Context<'_>,
+    #[description = "기록 번호 (최근 순)"]
+    #[min = 1]
+    index: usize,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let entry = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let guild_data = guild_data.lock().await;
+        guild_data
+            .song_history
+            .iter()
+            .rev()
+            .nth(index.saturating_sub(1))
+            .cloned()
+            .ok_or(Error::from("There is no song at that position in the history."))?
+    };
+
+    let library = data::Shared::get(ctx.serenity_context()).await.library.clone();
+    let source = entry
+        .source(library.as_deref())
+        .ok_or(Error::from("That song is no longer in the library."))?;
+
+    let reply = ctx.reply(source.to_string()).await?;
+    let message = reply.message().await?;
+
+    let request = data::song::Request::new(
+        source,
+        guild_id,
+        ctx.author().id,
+        ctx.channel_id(),
+        message.id,
+        ctx.locale(),
+    );
+
+    request_internal(ctx.serenity_context(), request).await?;
+    Ok(())
+}
+
+/// 대기열이 비었을 때 비슷한 노래를 자동으로 재생합니다.
+#[poise::command(slash_command)]
+pub async fn autoplay(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let idle = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.autoplay = enabled;
+        guild_data.save().await?;
+        guild_data.song_now.is_none() && guild_data.song_queue.is_empty()
+    };
+
+    ctx.reply(format!("autoplay : {}", if enabled { "on" } else { "off" })).await?;
+
+    if enabled && idle && get_internal(ctx.serenity_context(), guild_id).await.is_some() {
+        next_internal(ctx.serenity_context(), guild_id).await?;
+    }
+    Ok(())
+}
+
+/// 재생 중인 노래나 검색한 노래의 가사를 표시합니다.
+#[poise::command(slash_command, rename = "lyrics")]
+pub async fn lyrics_(
+    ctx: Context<'_>,
+    #[description = "가수 - 제목 (비워 두면 재생 중인 노래)"]
+    query: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let serenity_ctx = ctx.serenity_context();
+
+    let metadata = match query {
+        Some(query) => songbird::input::AuxMetadata {
+            title: Some(query),
+            ..Default::default()
+        },
+        None => {
+            let now = {
+                let guild_data = data::Storage::guild(serenity_ctx, ctx.guild_id().unwrap()).await;
+                let guild_data = guild_data.lock().await;
+                guild_data.song_now.as_ref().map(|now| now.request()).ok_or(SongError::NotPlaying)?
+            };
+            now.aux_metadata(serenity_ctx).await.unwrap_or_default()
+        }
+    };
+
+    let title = metadata.title.clone().unwrap_or_default();
+    match lyrics::fetch(serenity_ctx, &metadata).await {
+        Some(found) => ctx.send(poise::CreateReply::default().embed(lyrics::full_embed(&title, &found))).await?,
+        None => ctx.reply(format!("no lyrics found : {}", title)).await?,
+    };
+    Ok(())
+}
+
+/// 음향 효과를 설정합니다. 재생 중인 노래는 현재 위치부터 다시 재생됩니다.
+#[poise::command(slash_command)]
+pub async fn filter(
+    ctx: Context<'_>,
+    preset: song::FilterPreset,
+    #[description = "Custom : 재생 속도 (배)"]
+    #[min = 0.5]
+    #[max = 2.0]
+    tempo: Option<f64>,
+    #[description = "Custom : 음높이 (반음)"]
+    #[min = -12.0]
+    #[max = 12.0]
+    pitch: Option<f64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let filter = song::Filter {
+        preset,
+        tempo: tempo.unwrap_or(1.0),
+        pitch: pitch.unwrap_or(0.0),
+    };
+
+    let track = {
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_filter = filter;
+        guild_data.save().await?;
+        guild_data.song_now.as_ref().and_then(|now| now.track())
+    };
+
+    ctx.reply(format!("song filter : {}", filter)).await?;
+
+    let position = match track {
+        Some(track) => song::track_position(&track).await,
+        None => None,
+    };
+    if let Some(position) = position {
+        restart_internal(ctx.serenity_context(), guild_id, position).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.reply("song stop").await?;
+    stop_internal(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
+    Ok(())
+}
+
+/// 재생 중인 노래를 건너뜁니다. 투표 건너뛰기가 켜져 있으면 건너뛰기에 투표합니다.
 #[poise::command(slash_command)]
 pub async fn next(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.reply("song next").await?;
-    next_internal(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
+    ctx.defer().await?;
+
+    let result = skip_vote_internal(ctx.serenity_context(), ctx.guild_id().unwrap(), ctx.author().id).await?;
+    match result {
+        SkipResult::Skipped => ctx.reply("song next").await?,
+        SkipResult::Voted { votes, required } => ctx.reply(format!("vote skip {}/{}", votes, required)).await?,
+    };
     Ok(())
 }
 
@@ -356,7 +1983,7 @@ pub async fn ai(ctx: Context<'_>, singer: rvc::Model, song: String, pitch: Optio
     let reply = ctx.reply("...song ai fetching").await?;
 
     let target = target.unwrap_or_default();
-    let youtube = data::song::Source::Chat(song).get_youtube(ctx.serenity_context()).await?;
+    let youtube = data::song::youtube_dl(ctx.serenity_context(), &song).await?;
     let rvc_song = rvc::RVCSong::new(singer, youtube, pitch, target.should_download()).await?;    
     
     let name = rvc_song.title(ctx.locale());
@@ -392,4 +2019,34 @@ pub async fn ai(ctx: Context<'_>, singer: rvc::Model, song: String, pitch: Optio
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request() -> data::song::Request {
+        data::song::Request::new(
+            data::song::Source::Chat(String::from("song")),
+            serenity::GuildId::new(1),
+            serenity::UserId::new(1),
+            serenity::ChannelId::new(1),
+            serenity::MessageId::new(1),
+            None::<String>,
+        )
+    }
+
+    #[test]
+    fn check_limits_duration() {
+        let mut guild_data = data::Guild::new(serenity::GuildId::new(1));
+        assert!(check_limits(&guild_data, &request(), None).is_ok());
+
+        guild_data.song_config.max_duration = Some(60);
+        assert!(check_limits(&guild_data, &request(), Some(Duration::from_secs(60))).is_ok());
+        assert!(matches!(
+            check_limits(&guild_data, &request(), Some(Duration::from_secs(61))),
+            Err(SongError::DurationLimit(_))
+        ));
+        assert!(matches!(check_limits(&guild_data, &request(), None), Err(SongError::UnknownDuration(_))));
+    }
+}
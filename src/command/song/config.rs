@@ -0,0 +1,228 @@
+use crate::{data, data::song, prelude::*};
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("playlist_limit", "vote_skip", "dj_role", "permission", "scheduler", "queue_limit", "user_queue_limit", "duration_limit", "lyrics", "crossfade"),
+    subcommand_required
+)]
+pub async fn config(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// 재생 목록 링크에서 한 번에 추가할 최대 곡 수를 설정합니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn playlist_limit(
+    ctx: Context<'_>,
+    #[min = 1]
+    #[max = 200]
+    limit: usize,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.playlist_limit = limit;
+        guild_data.save().await?;
+    }
+
+    ctx.say(format!("playlist limit set to {}", limit)).await?;
+    Ok(())
+}
+
+/// 노래를 건너뛰는 데 필요한 투표 비율을 설정합니다. 비워 두면 투표 없이 바로 건너뜁니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn vote_skip(
+    ctx: Context<'_>,
+    #[description = "음성 채널 인원 중 필요한 투표 비율 (%)"]
+    #[min = 1]
+    #[max = 100]
+    percent: Option<u32>,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.vote_skip = percent;
+        guild_data.save().await?;
+    }
+
+    match percent {
+        Some(percent) => ctx.say(format!("vote skip set to {}%", percent)).await?,
+        None => ctx.say("vote skip disabled").await?,
+    };
+    Ok(())
+}
+
+/// DJ 역할을 설정합니다. 비워 두면 채널 관리 권한이 있는 사람만 DJ가 됩니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn dj_role(
+    ctx: Context<'_>,
+    #[description = "DJ 역할"]
+    role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.dj_role = role.as_ref().map(|role| role.id);
+        guild_data.save().await?;
+    }
+
+    match role {
+        Some(role) => ctx.say(format!("dj role set to {}", role.name)).await?,
+        None => ctx.say("dj role cleared").await?,
+    };
+    Ok(())
+}
+
+/// 재생 기능을 사용할 수 있는 사람을 설정합니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn permission(
+    ctx: Context<'_>,
+    #[description = "기능"]
+    action: song::Action,
+    #[description = "사용할 수 있는 사람"]
+    permission: song::Permission,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.permissions.insert(action, permission);
+        guild_data.save().await?;
+    }
+
+    ctx.say(format!("{} : {}", action, permission)).await?;
+    Ok(())
+}
+
+/// 대기열에서 다음 노래를 고르는 방식을 설정합니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn scheduler(
+    ctx: Context<'_>,
+    #[description = "Priority : 🔖 반응이 많은 순, Fifo : 신청한 순, RoundRobin : 신청자끼리 번갈아"]
+    policy: song::Scheduler,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_scheduler = policy;
+        guild_data.save().await?;
+    }
+
+    ctx.say(format!("scheduler set to {}", policy)).await?;
+    Ok(())
+}
+
+/// 대기열에 넣을 수 있는 최대 곡 수를 설정합니다. 비워 두면 제한하지 않습니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn queue_limit(
+    ctx: Context<'_>,
+    #[min = 1]
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.max_queue = limit;
+        guild_data.save().await?;
+    }
+
+    match limit {
+        Some(limit) => ctx.say(format!("queue limit set to {}", limit)).await?,
+        None => ctx.say("queue limit removed").await?,
+    };
+    Ok(())
+}
+
+/// 한 사람이 대기열에 넣을 수 있는 최대 곡 수를 설정합니다. 비워 두면 제한하지 않습니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn user_queue_limit(
+    ctx: Context<'_>,
+    #[min = 1]
+    limit: Option<usize>,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.max_user_queue = limit;
+        guild_data.save().await?;
+    }
+
+    match limit {
+        Some(limit) => ctx.say(format!("user queue limit set to {}", limit)).await?,
+        None => ctx.say("user queue limit removed").await?,
+    };
+    Ok(())
+}
+
+/// 대기열에 넣을 수 있는 노래의 최대 길이를 설정합니다. 비워 두면 제한하지 않습니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn duration_limit(
+    ctx: Context<'_>,
+    #[description = "최대 길이 (형식 : 10:00)"]
+    limit: Option<String>,
+) -> Result<(), Error> {
+    let limit = match limit {
+        Some(limit) => Some(song::parse_duration(&limit).ok_or(Error::from("Invalid duration. Use mm:ss."))?),
+        None => None,
+    };
+
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.max_duration = limit.map(|limit| limit.as_secs());
+        guild_data.save().await?;
+    }
+
+    match limit {
+        Some(limit) => ctx.say(format!("duration limit set to {}", song::format_duration(limit))).await?,
+        None => ctx.say("duration limit removed").await?,
+    };
+    Ok(())
+}
+
+/// 노래가 시작될 때 노래 채널에 가사를 표시할지 설정합니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn lyrics(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.lyrics = enabled;
+        guild_data.save().await?;
+    }
+
+    ctx.say(format!("lyrics : {}", if enabled { "on" } else { "off" })).await?;
+    Ok(())
+}
+
+/// 노래가 끝날 때 다음 노래와 겹쳐서 재생할 시간을 설정합니다. 비워 두면 겹치지 않습니다.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn crossfade(
+    ctx: Context<'_>,
+    #[description = "초 단위"]
+    #[min = 1]
+    #[max = 20]
+    seconds: Option<u64>,
+) -> Result<(), Error> {
+    {
+        let guild_id = ctx.guild_id().unwrap();
+        let guild_data = data::Storage::guild(ctx.serenity_context(), guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        guild_data.song_config.crossfade = seconds;
+        guild_data.save().await?;
+    }
+
+    match seconds {
+        Some(seconds) => ctx.say(format!("crossfade set to {}s", seconds)).await?,
+        None => ctx.say("crossfade off").await?,
+    };
+    Ok(())
+}
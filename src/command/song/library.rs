@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use crate::{data, prelude::*};
+use crate::data::song::{self, library::Library};
+
+const MAX_CHOICES: usize = 25;
+const MAX_CHOICE_LENGTH: usize = 100;
+const SEARCH_LIMIT: usize = 10;
+
+/// 로컬 음악 라이브러리에서 노래를 찾아 재생합니다.
+#[poise::command(slash_command, subcommands("search", "play", "album"), subcommand_required)]
+pub async fn library(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn get(ctx: &serenity::Context) -> Result<Arc<Library>, Error> {
+    data::Shared::get(ctx)
+        .await
+        .library
+        .clone()
+        .ok_or(Error::from("The music library is not configured."))
+}
+
+fn truncate(s: &str) -> String {
+    s.chars().take(MAX_CHOICE_LENGTH).collect()
+}
+
+async fn autocomplete_track(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let library = match get(ctx.serenity_context()).await {
+        Ok(library) => library,
+        Err(_) => return Vec::new(),
+    };
+
+    library
+        .search(partial, MAX_CHOICES)
+        .into_iter()
+        .map(|track| {
+            // long paths do not fit in a choice, so they are searched again by title
+            let path = track.path.to_string_lossy();
+            let value = if path.len() <= MAX_CHOICE_LENGTH { path.into_owned() } else { truncate(&track.title()) };
+            serenity::AutocompleteChoice::new(truncate(&track.title()), value)
+        })
+        .collect()
+}
+
+async fn autocomplete_album(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let library = match get(ctx.serenity_context()).await {
+        Ok(library) => library,
+        Err(_) => return Vec::new(),
+    };
+
+    library
+        .albums(partial, MAX_CHOICES)
+        .into_iter()
+        .map(|album| serenity::AutocompleteChoice::new(truncate(&album), truncate(&album)))
+        .collect()
+}
+
+/// 라이브러리에서 노래를 검색합니다.
+#[poise::command(slash_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "아티스트, 제목 또는 앨범"]
+    query: String,
+) -> Result<(), Error> {
+    let library = get(ctx.serenity_context()).await?;
+    let tracks = library.search(&query, SEARCH_LIMIT);
+    if tracks.is_empty() {
+        ctx.reply("no tracks found").await?;
+        return Ok(());
+    }
+
+    let description = tracks
+        .iter()
+        .enumerate()
+        .map(|(index, track)| {
+            let duration = track
+                .tags
+                .duration
+                .map(song::format_duration)
+                .unwrap_or_else(|| String::from("?:??"));
+            match &track.tags.album {
+                Some(album) => format!("`{}.` {} · {} · {}", index + 1, track.title(), album, duration),
+                None => format!("`{}.` {} · {}", index + 1, track.title(), duration),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let embed = serenity::CreateEmbed::new()
+        .title(format!("📚 {}", query))
+        .description(description)
+        .footer(serenity::CreateEmbedFooter::new(format!("{} tracks in library", library.num_tracks())));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// 라이브러리의 노래를 재생 목록에 추가합니다.
+#[poise::command(slash_command)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "노래"]
+    #[autocomplete = "autocomplete_track"]
+    track: String,
+) -> Result<(), Error> {
+    let library = get(ctx.serenity_context()).await?;
+    let track = library
+        .get(std::path::Path::new(&track))
+        .or_else(|| library.search(&track, 1).into_iter().next())
+        .ok_or(Error::from("Cannot find the track in the library."))?;
+
+    let reply = ctx.reply(format!("📚 {}", track.title())).await?;
+    let message = reply.message().await?;
+
+    let request = song::Request::new(
+        song::Source::Library(track),
+        ctx.guild_id().expect("This command can only be used within guilds."),
+        ctx.author().id,
+        ctx.channel_id(),
+        message.id,
+        ctx.locale(),
+    );
+
+    super::queue_internal(ctx.serenity_context(), Arc::new(request)).await?;
+    Ok(())
+}
+
+/// 라이브러리의 앨범을 재생 목록에 추가합니다.
+#[poise::command(slash_command)]
+pub async fn album(
+    ctx: Context<'_>,
+    #[description = "앨범"]
+    #[autocomplete = "autocomplete_album"]
+    mut album: String,
+) -> Result<(), Error> {
+    let library = get(ctx.serenity_context()).await?;
+    let mut tracks = library.album(&album);
+    if tracks.is_empty() {
+        // typed without picking a choice, or cut to fit in a choice
+        album = library
+            .albums(&album, 1)
+            .into_iter()
+            .next()
+            .ok_or(Error::from("Cannot find the album in the library."))?;
+        tracks = library.album(&album);
+    }
+
+    let guild_id = ctx.guild_id().expect("This command can only be used within guilds.");
+    let limit = data::Storage::guild(ctx.serenity_context(), guild_id)
+        .await
+        .lock()
+        .await
+        .song_config
+        .playlist_limit;
+
+    let summary = if tracks.len() > limit {
+        format!("💿 {} : queued {} of {} songs", album, limit, tracks.len())
+    } else {
+        format!("💿 {} : queued {} songs", album, tracks.len())
+    };
+    let reply = ctx.reply(summary).await?;
+    let message = reply.message().await?;
+
+    let group = song::RequestGroup::new();
+    let requests = tracks
+        .into_iter()
+        .take(limit)
+        .map(|track| {
+            Arc::new(
+                song::Request::new(
+                    song::Source::Library(track),
+                    guild_id,
+                    ctx.author().id,
+                    ctx.channel_id(),
+                    message.id,
+                    ctx.locale(),
+                )
+                .in_group(&group),
+            )
+        })
+        .collect();
+
+    super::queue_many_internal(ctx.serenity_context(), requests).await?;
+    Ok(())
+}
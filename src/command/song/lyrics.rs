@@ -0,0 +1,160 @@
+use poise::serenity_prelude::async_trait;
+use std::{sync::Arc, time::Duration};
+
+use crate::{data, prelude::*};
+use crate::data::song::{self, lyrics::Lyrics};
+
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Lines shown before and after the current line.
+const CONTEXT_LINES: usize = 3;
+const MAX_DESCRIPTION_LENGTH: usize = 4000;
+
+pub async fn fetch(ctx: &serenity::Context, metadata: &songbird::input::AuxMetadata) -> Option<Lyrics> {
+    let (artist, title) = song::lyrics::search_terms(metadata)?;
+    let shared = data::Shared::get(ctx).await;
+    song::lyrics::lookup(&shared.lyrics, &artist, &title, metadata.duration).await
+}
+
+pub fn full_embed(title: &str, lyrics: &Lyrics) -> serenity::CreateEmbed {
+    let mut text = lyrics.text();
+    if text.chars().count() > MAX_DESCRIPTION_LENGTH {
+        text = text.chars().take(MAX_DESCRIPTION_LENGTH).collect::<String>() + "…";
+    }
+
+    serenity::CreateEmbed::new()
+        .title(format!("🎤 {}", title))
+        .description(text)
+}
+
+fn synced_embed(title: &str, lyrics: &Lyrics, line: Option<usize>) -> serenity::CreateEmbed {
+    let center = line.unwrap_or(0);
+    let start = center.saturating_sub(CONTEXT_LINES);
+    let end = (center + CONTEXT_LINES + 1).min(lyrics.lines.len());
+
+    let description = (start..end)
+        .map(|index| {
+            let text = &lyrics.lines[index].text;
+            if line != Some(index) {
+                text.clone()
+            } else if text.is_empty() {
+                String::from("▶ ♪")
+            } else {
+                format!("▶ **{}**", text)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    serenity::CreateEmbed::new()
+        .title(format!("🎤 {}", title))
+        .description(description)
+}
+
+/// Looks up the lyrics of the song that just started, and posts them in the song channel.
+/// Synced lyrics are kept on the current line as the track plays.
+pub async fn start(
+    ctx: &serenity::Context,
+    request: Arc<song::Request>,
+    track: songbird::tracks::TrackHandle,
+) -> Result<(), Error> {
+    let guild_data = data::Storage::guild(ctx, request.guild_id).await;
+
+    let (channel_id, previous) = {
+        let mut guild_data = guild_data.lock().await;
+        let channel_id = match guild_data.channel_song {
+            Some(channel_id) if guild_data.song_config.lyrics => channel_id,
+            _ => return Ok(()),
+        };
+        (channel_id, guild_data.song_lyrics.take())
+    };
+
+    if let Some(message_id) = previous {
+        channel_id.delete_message(ctx, message_id).await.ok();
+    }
+
+    let metadata = match request.aux_metadata(ctx).await {
+        Some(metadata) => metadata,
+        None => return Ok(()),
+    };
+    let lyrics = match fetch(ctx, &metadata).await {
+        Some(lyrics) => lyrics,
+        None => return Ok(()),
+    };
+    let title = metadata.title.unwrap_or_else(|| request.source.to_string());
+
+    let embed = if lyrics.is_synced() {
+        synced_embed(&title, &lyrics, None)
+    } else {
+        full_embed(&title, &lyrics)
+    };
+    let message = channel_id
+        .send_message(ctx, serenity::CreateMessage::new().embed(embed))
+        .await?;
+    guild_data.lock().await.song_lyrics = Some(message.id);
+
+    if lyrics.is_synced() {
+        track.add_event(
+            songbird::Event::Periodic(UPDATE_INTERVAL, None),
+            UpdateHandler {
+                context: ctx.clone(),
+                channel_id,
+                message_id: message.id,
+                title,
+                lyrics,
+                line: std::sync::Mutex::new(None),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn start_nowait(
+    ctx: &serenity::Context,
+    request: Arc<song::Request>,
+    track: songbird::tracks::TrackHandle,
+) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        start(&ctx, request, track).await.ok();
+    });
+}
+
+struct UpdateHandler {
+    context: serenity::Context,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    title: String,
+    lyrics: Lyrics,
+    line: std::sync::Mutex<Option<usize>>,
+}
+
+#[async_trait]
+impl songbird::EventHandler for UpdateHandler {
+    async fn act(&self, ctx: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        let position = match ctx {
+            songbird::EventContext::Track(tracks) => {
+                let (state, track) = tracks.first()?;
+                song::TrackOffset::song_position(track, state.position).await
+            }
+            _ => return None,
+        };
+
+        let line = self.lyrics.line_at(position);
+        {
+            let mut current = self.line.lock().unwrap();
+            if *current == line {
+                return None;
+            }
+            *current = line;
+        }
+
+        let edit = serenity::EditMessage::new().embed(synced_embed(&self.title, &self.lyrics, line));
+        match self.channel_id.edit_message(&self.context, self.message_id, edit).await {
+            Ok(_) => None,
+            // the message is deleted, or the next song has started
+            Err(_) => Some(songbird::Event::Cancel),
+        }
+    }
+}
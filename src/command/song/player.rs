@@ -0,0 +1,252 @@
+use poise::serenity_prelude::async_trait;
+use std::time::Duration;
+
+use crate::{data, prelude::*};
+use crate::data::song;
+
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+
+const BUTTON_PREFIX: &str = "song_player_";
+const BUTTON_PAUSE: &str = "song_player_pause";
+const BUTTON_NEXT: &str = "song_player_next";
+const BUTTON_STOP: &str = "song_player_stop";
+const BUTTON_REPEAT: &str = "song_player_repeat";
+
+const PROGRESS_BAR_LENGTH: usize = 20;
+
+fn progress_bar(position: Duration, total: Duration) -> String {
+    let ratio = if total.is_zero() {
+        0.0
+    } else {
+        (position.as_secs_f64() / total.as_secs_f64()).min(1.0)
+    };
+    let head = ((PROGRESS_BAR_LENGTH - 1) as f64 * ratio) as usize;
+
+    (0..PROGRESS_BAR_LENGTH)
+        .map(|index| if index == head { '🔘' } else { '▬' })
+        .collect()
+}
+
+fn components(paused: bool, repeat: song::RepeatMode) -> Vec<serenity::CreateActionRow> {
+    vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(BUTTON_PAUSE)
+            .emoji(if paused { '▶' } else { '⏸' })
+            .style(serenity::ButtonStyle::Secondary),
+        serenity::CreateButton::new(BUTTON_NEXT)
+            .emoji('⏭')
+            .style(serenity::ButtonStyle::Secondary),
+        serenity::CreateButton::new(BUTTON_STOP)
+            .emoji('⏹')
+            .style(serenity::ButtonStyle::Danger),
+        serenity::CreateButton::new(BUTTON_REPEAT)
+            .emoji(repeat.emoji().unwrap_or('🔁'))
+            .style(match repeat {
+                song::RepeatMode::Off => serenity::ButtonStyle::Secondary,
+                _ => serenity::ButtonStyle::Primary,
+            }),
+    ])]
+}
+
+async fn embed(
+    ctx: &serenity::Context,
+    now: Option<(std::sync::Arc<song::Request>, Option<songbird::tracks::TrackHandle>, bool)>,
+    repeat: song::RepeatMode,
+    volume: u32,
+    num_queue: usize,
+    skip_votes: usize,
+) -> serenity::CreateEmbed {
+    let footer = serenity::CreateEmbedFooter::new(format!(
+        "repeat {} · volume {}% · {} songs queued",
+        repeat, volume, num_queue
+    ));
+
+    let (request, track, paused) = match now {
+        Some(now) => now,
+        None => {
+            return serenity::CreateEmbed::new()
+                .title("Nothing is playing")
+                .footer(footer);
+        }
+    };
+
+    let metadata = request.aux_metadata(ctx).await.unwrap_or_default();
+    let title = metadata.title.unwrap_or_else(|| request.source.to_string());
+
+    let position = match track {
+        Some(track) => song::track_position(&track).await,
+        None => None,
+    };
+
+    let description = match (position, metadata.duration) {
+        (Some(position), Some(total)) => format!(
+            "{}\n`{} / {}`",
+            progress_bar(position, total),
+            song::format_duration(position),
+            song::format_duration(total)
+        ),
+        (Some(position), None) => format!("`{}`", song::format_duration(position)),
+        (None, _) => String::from("loading..."),
+    };
+
+    let mut embed = serenity::CreateEmbed::new()
+        .title(format!("{} {}", if paused { '⏸' } else { '🎵' }, title))
+        .description(description)
+        .field("requested by", format!("<@{}>", request.author_id), true)
+        .footer(footer);
+    if let Some(live_title) = request.live_title.get() {
+        embed = embed.field("on air", live_title, false);
+    }
+    if skip_votes > 0 {
+        embed = embed.field("skip votes", skip_votes.to_string(), true);
+    }
+    if let Some(url) = metadata.source_url {
+        embed = embed.url(url);
+    }
+    if let Some(thumbnail) = metadata.thumbnail {
+        embed = embed.thumbnail(thumbnail);
+    }
+    embed
+}
+
+/// Renders the player panel in the song channel, creating it if it does not exist.
+pub async fn update(ctx: &serenity::Context, guild_id: serenity::GuildId) -> Result<(), Error> {
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+
+    // updates running at once would each find no panel and send a new one
+    let update_lock = guild_data.lock().await.song_player_update.clone();
+    let _update_lock = update_lock.lock().await;
+
+    let (channel_id, message_id, now, repeat, volume, num_queue, skip_votes) = {
+        let guild_data = guild_data.lock().await;
+        let channel_id = match guild_data.channel_song {
+            Some(channel_id) => channel_id,
+            None => return Ok(()),
+        };
+        let now = guild_data
+            .song_now
+            .as_ref()
+            .map(|now| (now.request(), now.track(), now.is_paused()));
+        (
+            channel_id,
+            guild_data.song_player,
+            now,
+            guild_data.song_repeat,
+            guild_data.song_config.volume,
+            guild_data.song_queue.len(),
+            guild_data.song_skip_votes.len(),
+        )
+    };
+
+    let paused = now.as_ref().is_some_and(|now| now.2);
+    let embed = embed(ctx, now, repeat, volume, num_queue, skip_votes).await;
+    let components = components(paused, repeat);
+
+    if let Some(message_id) = message_id {
+        let edit = serenity::EditMessage::new()
+            .embed(embed.clone())
+            .components(components.clone());
+        match channel_id.edit_message(ctx, message_id, edit).await {
+            Ok(_) => return Ok(()),
+            Err(serenity::Error::Http(err))
+                if err.status_code().is_some_and(|status| status.as_u16() == 404) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let message = channel_id
+        .send_message(ctx, serenity::CreateMessage::new().embed(embed).components(components))
+        .await?;
+
+    let mut guild_data = guild_data.lock().await;
+    guild_data.song_player = Some(message.id);
+    guild_data.save().await?;
+    Ok(())
+}
+
+pub fn update_nowait(ctx: &serenity::Context, guild_id: serenity::GuildId) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        update(&ctx, guild_id).await.ok();
+    });
+}
+
+pub struct UpdateHandler {
+    pub context: serenity::Context,
+    pub guild_id: serenity::GuildId,
+}
+
+#[async_trait]
+impl songbird::EventHandler for UpdateHandler {
+    async fn act(&self, _: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        update(&self.context, self.guild_id).await.ok();
+        None
+    }
+}
+
+async fn followup(
+    ctx: &serenity::Context,
+    interaction: &serenity::ComponentInteraction,
+    content: String,
+) -> Result<(), Error> {
+    interaction
+        .create_followup(
+            ctx,
+            serenity::CreateInteractionResponseFollowup::new().content(content).ephemeral(true),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn handle_button(
+    ctx: &serenity::Context,
+    interaction: &serenity::ComponentInteraction,
+) -> Result<(), Error> {
+    if !interaction.data.custom_id.starts_with(BUTTON_PREFIX) {
+        return Ok(());
+    }
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    interaction
+        .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let action = match interaction.data.custom_id.as_str() {
+        BUTTON_PAUSE => song::Action::Pause,
+        BUTTON_NEXT => song::Action::Next,
+        BUTTON_STOP => song::Action::Stop,
+        BUTTON_REPEAT => song::Action::Repeat,
+        _ => return Ok(()),
+    };
+    if let Err(err) = super::check_permission(ctx, guild_id, interaction.user.id, action).await {
+        return followup(ctx, interaction, err.to_string()).await;
+    }
+
+    match interaction.data.custom_id.as_str() {
+        BUTTON_PAUSE => {
+            let paused = {
+                let guild_data = data::Storage::guild(ctx, guild_id).await;
+                let guild_data = guild_data.lock().await;
+                guild_data.song_now.as_ref().is_some_and(|now| now.is_paused())
+            };
+            super::pause_internal(ctx, guild_id, !paused).await?;
+        }
+        BUTTON_NEXT => match super::skip_vote_internal(ctx, guild_id, interaction.user.id).await {
+            Ok(super::SkipResult::Skipped) => {}
+            Ok(super::SkipResult::Voted { votes, required }) => {
+                followup(ctx, interaction, format!("vote skip {}/{}", votes, required)).await?
+            }
+            Err(err) => followup(ctx, interaction, err.to_string()).await?,
+        },
+        BUTTON_STOP => super::stop_internal(ctx, guild_id).await?,
+        BUTTON_REPEAT => {
+            let repeat = data::Storage::guild(ctx, guild_id).await.lock().await.song_repeat;
+            super::repeat_internal(ctx, guild_id, repeat.next()).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
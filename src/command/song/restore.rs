@@ -0,0 +1,305 @@
+use poise::serenity_prelude::async_trait;
+use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
+
+use crate::{data, prelude::*};
+use crate::data::song;
+
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+const BUTTON_PREFIX: &str = "song_restore_";
+const BUTTON_RESUME: &str = "song_restore_resume";
+const BUTTON_DISCARD: &str = "song_restore_discard";
+
+/// Writes the current song and the queue to the queue file of the guild.
+pub async fn save(ctx: &serenity::Context, guild_id: serenity::GuildId) -> Result<(), Error> {
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+
+    // the snapshot is taken under the lock, so the last save to write has the latest one
+    let save_lock = guild_data.lock().await.song_queue_save.clone();
+    let _save_lock = save_lock.lock().await;
+
+    let (now, queue) = {
+        let guild_data = guild_data.lock().await;
+        let now = guild_data.song_now.as_ref().map(|now| (now.request(), now.track()));
+        let queue: Vec<_> = guild_data
+            .song_queue
+            .iter()
+            .filter_map(|request| song::SavedRequest::new(request))
+            .collect();
+        (now, queue)
+    };
+
+    let position = match now.as_ref().and_then(|(_, track)| track.as_ref()) {
+        Some(track) => song::track_position(track).await,
+        None => None,
+    };
+
+    let voice_channel = match super::get_internal(ctx, guild_id).await {
+        Some(call) => call
+            .lock()
+            .await
+            .current_channel()
+            .map(|channel_id| serenity::ChannelId::new(channel_id.0.get())),
+        None => None,
+    };
+
+    let saved_queue = song::SavedQueue {
+        voice_channel,
+        now: now.and_then(|(request, _)| song::SavedRequest::new(&request)),
+        position,
+        queue,
+    };
+
+    data::Guild::save_song_queue(guild_id, &saved_queue).await
+}
+
+pub fn save_nowait(ctx: &serenity::Context, guild_id: serenity::GuildId) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        save(&ctx, guild_id).await.ok();
+    });
+}
+
+pub struct SaveHandler {
+    pub context: serenity::Context,
+    pub guild_id: serenity::GuildId,
+}
+
+#[async_trait]
+impl songbird::EventHandler for SaveHandler {
+    async fn act(&self, _: &songbird::EventContext<'_>) -> Option<songbird::Event> {
+        save(&self.context, self.guild_id).await.ok();
+        None
+    }
+}
+
+/// Restored requests of a message, which share its voters and reactions.
+struct RestoredMessage {
+    voters: Option<HashSet<serenity::UserId>>,
+    group: Arc<song::RequestGroup>,
+}
+
+async fn restore_request(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    messages: &mut HashMap<serenity::MessageId, RestoredMessage>,
+    saved_request: song::SavedRequest,
+) -> Option<Arc<song::Request>> {
+    let library = data::Shared::get(ctx).await.library.clone();
+    let request = saved_request.restore(guild_id, library.as_deref())?;
+
+    // reactions made while offline are missed by the events, so they are fetched once here
+    let message = match messages.entry(request.message_id) {
+        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        std::collections::hash_map::Entry::Vacant(entry) => {
+            let voters = match ctx.http.get_message(request.channel_id, request.message_id).await {
+                Ok(_) => Some(request.fetch_voters(ctx).await.unwrap_or_default()),
+                Err(_) => None,
+            };
+            entry.insert(RestoredMessage {
+                voters,
+                group: song::RequestGroup::new(),
+            })
+        }
+    };
+    let voters = message.voters.clone()?;
+
+    let request = request.in_group(&message.group);
+    *request.voters.lock().unwrap() = voters;
+    Some(Arc::new(request))
+}
+
+/// Loads the queue saved before restart, and asks in the song channel whether to resume it.
+pub async fn restore(ctx: &serenity::Context, guild_id: serenity::GuildId) -> Result<(), Error> {
+    let saved_queue = match data::Guild::load_song_queue(guild_id).await {
+        Ok(saved_queue) => saved_queue,
+        Err(_) => return Ok(()),
+    };
+    if saved_queue.is_empty() {
+        return Ok(());
+    }
+
+    // library tracks are found by path, so the index must be built first
+    if let Some(library) = data::Shared::get(ctx).await.library.clone() {
+        library.scanned().await;
+    }
+
+    let mut messages = HashMap::new();
+    let now = match saved_queue.now {
+        Some(saved_request) => restore_request(ctx, guild_id, &mut messages, saved_request).await,
+        None => None,
+    };
+    let mut queue = Vec::with_capacity(saved_queue.queue.len());
+    for saved_request in saved_queue.queue {
+        if let Some(request) = restore_request(ctx, guild_id, &mut messages, saved_request).await {
+            queue.push(request);
+        }
+    }
+
+    let restored_queue = song::RestoredQueue {
+        voice_channel: saved_queue.voice_channel,
+        position: now.as_ref().and(saved_queue.position),
+        now,
+        queue,
+    };
+    let num_requests = restored_queue.requests().count();
+    if num_requests == 0 {
+        data::Guild::save_song_queue(guild_id, &song::SavedQueue::default()).await?;
+        return Ok(());
+    }
+
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+    let channel_id = {
+        let guild_data = guild_data.lock().await;
+        guild_data
+            .channel_song
+            .or_else(|| restored_queue.requests().next().map(|request| request.channel_id))
+    };
+    let channel_id = match channel_id {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+
+    let content = match restored_queue.voice_channel {
+        Some(voice_channel) => format!(
+            "found {} songs from before restart. resume in <#{}>?",
+            num_requests, voice_channel
+        ),
+        None => format!("found {} songs from before restart. resume?", num_requests),
+    };
+    let components = vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(BUTTON_RESUME)
+            .label("resume")
+            .style(serenity::ButtonStyle::Primary),
+        serenity::CreateButton::new(BUTTON_DISCARD)
+            .label("discard")
+            .style(serenity::ButtonStyle::Secondary),
+    ])];
+    channel_id
+        .send_message(ctx, serenity::CreateMessage::new().content(content).components(components))
+        .await?;
+
+    guild_data.lock().await.song_restore = Some(restored_queue);
+    Ok(())
+}
+
+async fn resume(ctx: &serenity::Context, guild_id: serenity::GuildId, restored_queue: song::RestoredQueue) -> Result<(), Error> {
+    if let Some(voice_channel) = restored_queue.voice_channel {
+        super::join_channel_internal(ctx, guild_id, voice_channel).await?;
+    }
+
+    let start_now = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        for request in restored_queue.queue.iter().rev() {
+            guild_data.song_queue.push_front(request.clone());
+        }
+        guild_data.song_now.is_none()
+    };
+
+    for request in restored_queue.queue.iter() {
+        request.clone().set_state_nowait(ctx.clone(), song::RequestState::Queue);
+    }
+
+    if !start_now {
+        if let Some(now) = restored_queue.now {
+            super::queue_internal(ctx, now).await?;
+        }
+        return Ok(());
+    }
+
+    match restored_queue.now {
+        Some(now) => {
+            let position = restored_queue.position.unwrap_or_default();
+            match super::play_from_internal(ctx, now.clone(), position, std::time::Duration::ZERO).await {
+                Ok(Some(_)) => {}
+                Ok(None) => super::next_internal(ctx, guild_id).await?,
+                Err(_) => {
+                    now.set_state_nowait(ctx.clone(), song::RequestState::Canceled);
+                    super::next_internal(ctx, guild_id).await?;
+                }
+            }
+        }
+        None => super::next_internal(ctx, guild_id).await?,
+    }
+
+    Ok(())
+}
+
+pub async fn handle_button(
+    ctx: &serenity::Context,
+    interaction: &serenity::ComponentInteraction,
+) -> Result<(), Error> {
+    if !interaction.data.custom_id.starts_with(BUTTON_PREFIX) {
+        return Ok(());
+    }
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let action = match interaction.data.custom_id.as_str() {
+        BUTTON_RESUME => song::Action::Join,
+        BUTTON_DISCARD => song::Action::Stop,
+        _ => return Ok(()),
+    };
+
+    let restored_queue = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let mut guild_data = guild_data.lock().await;
+        let permitted = match guild_data.song_restore.as_ref() {
+            Some(restored_queue) => {
+                let requests: Vec<_> = restored_queue.requests().cloned().collect();
+                super::is_permitted(ctx, &guild_data, interaction.user.id, action, &requests)
+            }
+            None => true,
+        };
+        if !permitted {
+            drop(guild_data);
+            interaction
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .content(super::SongError::Permission.to_string())
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+        guild_data.song_restore.take()
+    };
+
+    let content = match (&restored_queue, interaction.data.custom_id.as_str()) {
+        (None, _) => "already handled",
+        (Some(_), BUTTON_RESUME) => "resumed",
+        (Some(_), _) => "discarded",
+    };
+    interaction
+        .create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    let restored_queue = match restored_queue {
+        Some(restored_queue) => restored_queue,
+        None => return Ok(()),
+    };
+
+    if interaction.data.custom_id == BUTTON_RESUME {
+        resume(ctx, guild_id, restored_queue).await?;
+    } else {
+        for request in restored_queue.requests() {
+            request.clone().set_state_nowait(ctx.clone(), song::RequestState::Canceled);
+        }
+        save(ctx, guild_id).await?;
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use crate::prelude::*;
 use serde::{Deserialize, Serialize};
 use song::RequestState;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     mem,
     sync::Arc,
 };
@@ -21,6 +21,11 @@ impl serenity::prelude::TypeMapKey for SharedKey {
 pub struct Shared {
     pub http_client: reqwest::Client,
     pub spotify: rspotify::ClientCredsSpotify,
+    /// Tried in order.
+    pub lyrics: Vec<Box<dyn song::lyrics::Provider>>,
+    pub library: Option<Arc<song::library::Library>>,
+    /// Tried in order, the first one matching a link resolves it.
+    pub providers: Vec<Box<dyn song::provider::Provider>>,
 }
 
 impl Shared {
@@ -74,11 +79,49 @@ pub struct Guild {
     pub channel_song: Option<serenity::ChannelId>,
     pub users: HashMap<serenity::UserId, User>,
 
+    #[serde(default)]
+    pub song_config: song::Config,
+
+    #[serde(default)]
+    pub song_repeat: song::RepeatMode,
+
+    #[serde(default)]
+    pub song_scheduler: song::Scheduler,
+
+    #[serde(default)]
+    pub song_filter: song::Filter,
+
+    #[serde(default)]
+    pub song_player: Option<serenity::MessageId>,
+
+    /// Held while the player panel is updated, so that only one panel is created.
+    #[serde(skip)]
+    pub song_player_update: Arc<serenity::prelude::Mutex<()>>,
+
+    #[serde(default)]
+    pub song_history: VecDeque<song::HistoryEntry>,
+
     #[serde(skip)]
     pub song_now: Option<song::Now>,
 
     #[serde(skip)]
     pub song_queue: VecDeque<Arc<song::Request>>,
+
+    #[serde(skip)]
+    pub song_restore: Option<song::RestoredQueue>,
+
+    /// Held while the queue file is written, so that an older snapshot cannot overwrite a newer one.
+    #[serde(skip)]
+    pub song_queue_save: Arc<serenity::prelude::Mutex<()>>,
+
+    #[serde(skip)]
+    pub song_prefetch: Option<song::Prefetch>,
+
+    #[serde(skip)]
+    pub song_skip_votes: HashSet<serenity::UserId>,
+
+    #[serde(skip)]
+    pub song_lyrics: Option<serenity::MessageId>,
 }
 
 impl Guild {
@@ -89,8 +132,20 @@ impl Guild {
             channel_notify: None,
             channel_song: None,
             users: HashMap::new(),
+            song_config: song::Config::default(),
+            song_repeat: song::RepeatMode::default(),
+            song_scheduler: song::Scheduler::default(),
+            song_filter: song::Filter::default(),
+            song_player: None,
+            song_player_update: Arc::default(),
+            song_history: VecDeque::new(),
             song_now: None,
             song_queue: VecDeque::new(),
+            song_restore: None,
+            song_queue_save: Arc::default(),
+            song_prefetch: None,
+            song_skip_votes: HashSet::new(),
+            song_lyrics: None,
         }
     }
 
@@ -125,6 +180,31 @@ impl Guild {
         Ok(())
     }
 
+    fn song_queue_file_path(guild_id: serenity::GuildId) -> std::path::PathBuf {
+        std::path::Path::new(Self::FILE_DIR).join(format!("{}.queue", Self::file_name(guild_id)))
+    }
+
+    pub async fn load_song_queue(guild_id: serenity::GuildId) -> Result<song::SavedQueue, Error> {
+        let mut file = tokio::fs::File::open(Self::song_queue_file_path(guild_id)).await?;
+        let mut str = String::new();
+        file.read_to_string(&mut str).await?;
+        Ok(serde_json::from_str(&str)?)
+    }
+
+    /// Written to a temporary file first, so that the queue file is never left half written.
+    pub async fn save_song_queue(guild_id: serenity::GuildId, saved_queue: &song::SavedQueue) -> Result<(), Error> {
+        tokio::fs::create_dir_all(Self::FILE_DIR).await?;
+        let file_path = Self::song_queue_file_path(guild_id);
+        let temp_path = file_path.with_extension("queue.tmp");
+
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let file_str = serde_json::to_string_pretty(saved_queue)?;
+        file.write_all(file_str.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(temp_path, file_path).await?;
+        Ok(())
+    }
+
     pub fn channel(&self, channel_id: serenity::ChannelId) -> Option<&Channel> {
         self.channels.get(&channel_id)
     }
@@ -141,12 +221,62 @@ impl Guild {
         self.users.entry(user_id).or_default()
     }
 
+    const MAX_SONG_HISTORY: usize = 200;
+
+    fn song_history_push(&mut self, request: &song::Request, state: song::RequestState) {
+        self.song_history.push_back(song::HistoryEntry::new(request, state));
+        while self.song_history.len() > Self::MAX_SONG_HISTORY {
+            self.song_history.pop_front();
+        }
+    }
+
     pub fn song_now_complete(&mut self, ctx: &serenity::Context) {
         if let Some(now) = self.song_now.take() {
+            self.song_history_push(&now.request(), song::RequestState::Done);
             now.request().set_state_nowait(ctx.clone(), song::RequestState::Done);
         }
     }
 
+    /// Completes the current song and puts it back at the end of the queue.
+    pub fn song_now_requeue(&mut self, ctx: &serenity::Context) {
+        if let Some(now) = self.song_now.take() {
+            let request = now.request();
+            self.song_history_push(&request, song::RequestState::Done);
+            self.song_queue.push_back(request.clone());
+            request.set_state_nowait(ctx.clone(), song::RequestState::Queue);
+        }
+    }
+
+    /// Completes the current song according to the repeat mode, and returns it if it should be played again.
+    pub fn song_now_finish(&mut self, ctx: &serenity::Context) -> Option<Arc<song::Request>> {
+        match self.song_repeat {
+            song::RepeatMode::Off => {
+                self.song_now_complete(ctx);
+                None
+            }
+            song::RepeatMode::Track => self.song_now.take().map(|now| now.request()),
+            song::RepeatMode::Queue => {
+                self.song_now_requeue(ctx);
+                None
+            }
+        }
+    }
+
+    /// Whether `track` plays the current song, and is not one fading out.
+    pub fn song_now_is(&self, track: &songbird::tracks::TrackHandle) -> bool {
+        self.song_now
+            .as_ref()
+            .and_then(|now| now.track())
+            .is_some_and(|now| now.uuid() == track.uuid())
+    }
+
+    pub fn song_queue_shuffle(&mut self) {
+        use rand::seq::SliceRandom;
+        self.song_queue
+            .make_contiguous()
+            .shuffle(&mut rand::thread_rng());
+    }
+
     pub fn song_now_cancel(&mut self, ctx: &serenity::Context) {
         if let Some(now) = self.song_now.take() {
             now.request().cancel();
@@ -157,57 +287,55 @@ impl Guild {
                 RequestState::Playing => RequestState::Skipped,
                 _ => state,
             };
+
+            if let RequestState::Skipped = new_state {
+                self.song_history_push(&now.request(), new_state);
+            }
             
             now.request().set_state_nowait(ctx.clone(), new_state);
         }
     }
 
-    pub async fn song_queue_take(&mut self, ctx: &serenity::Context) -> Option<Arc<song::Request>> {
-        async fn num_queue_reactions(ctx: &serenity::Context, request: &song::Request) -> usize {
-            let emoji_queue = song::RequestState::Queue.emoji().unwrap();
-            match ctx
-                .http
-                .get_reaction_users(
-                    request.channel_id,
-                    request.message_id,
-                    &emoji_queue.into(),
-                    8,
-                    None,
-                )
-                .await
-            {
-                Ok(users) => users
-                    .iter()
-                    .filter(|user| !user.bot)
-                    .collect::<Vec<_>>()
-                    .len(),
-                _ => 0,
-            }
+    /// Skips the songs before `index` in the queue, and takes the one at it.
+    pub fn song_queue_skip_to(&mut self, ctx: &serenity::Context, index: usize) -> Option<Arc<song::Request>> {
+        let skipped: Vec<_> = self.song_queue.drain(..index).collect();
+        for request in skipped {
+            self.song_history_push(&request, song::RequestState::Skipped);
+            request.set_state_nowait(ctx.clone(), song::RequestState::Skipped);
         }
+        self.song_queue.pop_front()
+    }
 
-        // (index, priority)
-        let mut max: Option<(usize, usize)> = None;
-        for (index, priority) in self
-            .song_queue
-            .iter()
-            .map(|request| num_queue_reactions(ctx, request))
-            .enumerate()
-        {
-            let priority = priority.await;
-            
-            let replace = match max {
-                Some((_, max_priority)) => priority > max_priority,
-                None => true,
-            };
-            if replace {
-                max = Some((index, priority));
-            }
-        }
+    pub fn song_queue_take(&mut self) -> Option<Arc<song::Request>> {
+        let queue: Vec<_> = self.song_queue.iter().cloned().collect();
+        self.song_scheduler
+            .select(&queue, &self.song_recent_authors())
+            .and_then(|index| self.song_queue.remove(index))
+    }
 
-        max.and_then(|max| self.song_queue.remove(max.0))
+    /// Requests made by the message, including the ones waiting to be restored.
+    pub fn song_requests_of(&self, message_id: serenity::MessageId) -> Vec<Arc<song::Request>> {
+        self.song_now
+            .as_ref()
+            .map(|now| now.request())
+            .into_iter()
+            .chain(self.song_queue.iter().cloned())
+            .chain(self.song_restore.iter().flat_map(|restore| restore.requests().cloned()))
+            .filter(|request| request.message_id == message_id)
+            .collect()
+    }
+
+    /// Requesters of the current and previous songs, the most recent first.
+    pub fn song_recent_authors(&self) -> Vec<serenity::UserId> {
+        let now = self.song_now.as_ref().map(|now| now.request().author_id);
+        now.into_iter()
+            .chain(self.song_history.iter().rev().map(|entry| entry.author_id))
+            .collect()
     }
 
     pub fn song_queue_clear(&mut self, ctx: &serenity::Context) {
+        self.song_prefetch = None;
+
         let song_queue = {
             // clear queue
             let mut song_queue: VecDeque<Arc<data::song::Request>> = VecDeque::new();
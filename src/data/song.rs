@@ -1,66 +1,240 @@
 use rspotify::clients::BaseClient;
+use rspotify::model::Id;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use songbird::input::Compose;
+use tokio::io::AsyncBufReadExt;
 
 use crate::prelude::*;
 
 #[cfg(feature = "rvc")]
 use crate::rvc;
 
+pub mod attachment;
+pub mod library;
+pub mod lyrics;
+pub mod provider;
+pub mod stream;
+pub mod tags;
 
-pub enum SongLinkType {
-    Youtube,
-    Spotify(String),
-    Search,
+
+pub enum Source {
+
+    Chat(String),
+
+    Attachment(attachment::Attachment),
+
+    Library(std::sync::Arc<library::Track>),
+
+    #[cfg(feature = "rvc")]
+    RVC(rvc::RVCSong),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Chat(str) => write!(f, "{}", str),
+            Self::Attachment(attachment) => write!(f, "{}", attachment),
+            Self::Library(track) => write!(f, "{}", track),
+            #[cfg(feature = "rvc")]
+            Self::RVC(rvc_song) => write!(f, "{}", rvc_song),
+        }
+    }
+}
+
+async fn youtubedl_get_title_async(mut youtubedl: songbird::input::YoutubeDl, optional_artist: Option<String>) -> Option<String> {
+    let mut metadata = youtubedl.aux_metadata().await.ok()?;
+    if optional_artist.is_some() {
+        metadata.artist = optional_artist;
+    }
+    metadata_title(&metadata)
+}
+
+/// "artist - title", or the title if the artist is unknown.
+fn metadata_title(metadata: &songbird::input::AuxMetadata) -> Option<String> {
+    let title = metadata.title.as_ref()?;
+    match metadata.artist.as_ref() {
+        Some(artist) => Some(format!("{} - {}", artist, title)),
+        None => Some(title.clone()),
+    }
+}
+
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Parses `ss`, `mm:ss` or `hh:mm:ss`.
+pub fn parse_duration(s: &str) -> Option<std::time::Duration> {
+    let mut secs: u64 = 0;
+    let mut num_parts = 0;
+    for part in s.trim().split(':') {
+        num_parts += 1;
+        if num_parts > 3 {
+            return None;
+        }
+        secs = secs.checked_mul(60)?.checked_add(part.trim().parse::<u64>().ok()?)?;
+    }
+    Some(std::time::Duration::from_secs(secs))
+}
+
+#[derive(Deserialize)]
+struct YoutubeSearchEntry {
+    id: Option<String>,
+    url: Option<String>,
+    title: Option<String>,
+    channel: Option<String>,
+    duration: Option<f64>,
 }
 
-impl SongLinkType {
-    pub fn new(s: &str) -> Self {
-        let link_trim = s
-            .trim()
-            .trim_start_matches("http://")
-            .trim_start_matches("https://");
+/// yt-dlp is killed when the search is dropped, such as on a timeout.
+pub async fn youtube_search(query: &str, num_results: usize) -> Result<Vec<songbird::input::AuxMetadata>, Error> {
+    let output = tokio::process::Command::new("yt-dlp")
+        .arg("--flat-playlist")
+        .arg("-j")
+        .arg(format!("ytsearch{}:{}", num_results, query))
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
 
-        let mut link_split = link_trim.split('/');
-        
-        if let Some(domain) = link_split.next() {
-            if domain.contains("youtube") || domain.contains("youtu.be") {
-                return SongLinkType::Youtube;
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .filter_map(|line| serde_json::from_str::<YoutubeSearchEntry>(line).ok())
+        .map(|entry| songbird::input::AuxMetadata {
+            source_url: entry
+                .url
+                .or_else(|| entry.id.map(|id| format!("https://www.youtube.com/watch?v={}", id))),
+            title: entry.title,
+            channel: entry.channel,
+            duration: entry.duration.and_then(|duration| std::time::Duration::try_from_secs_f64(duration).ok()),
+            ..Default::default()
+        })
+        .collect())
+}
+
+const SPOTIFY_MARKET: rspotify::model::Market = rspotify::model::Market::Country(rspotify::model::Country::KoreaRepublicOf);
+
+async fn spotify_request<T, F, Fut>(spotify: &rspotify::ClientCredsSpotify, request: F) -> Result<T, Error>
+where
+    F: Fn() -> Fut,
+    Fut: futures::Future<Output = rspotify::ClientResult<T>>,
+{
+    loop {
+        match request().await {
+            Ok(result) => return Ok(result),
+            Err(rspotify::ClientError::InvalidToken) => {
+                spotify.request_token().await?;
             }
-            if domain.contains("spotify") {
-                if let Some(track_url) = link_split.nth(1) {
-                    let mut track_id = track_url;
-                    if let Some(si_pos) = track_id.find("?si=") {
-                        track_id = &track_url[..(si_pos)];
-                    }
-                    return SongLinkType::Spotify(track_id.to_string());
-                };
+            Err(err) => {
+                return Err(Box::new(err));
             }
         }
-        
-        SongLinkType::Search
     }
 }
 
-pub enum Source {
+fn spotify_search_query(artists: &[rspotify::model::SimplifiedArtist], name: &str) -> String {
+    let mut search_str: String = String::with_capacity(64);
+    search_str.push_str("music ");
+    search_str.push_str(&artists.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>().join(", "));
+    search_str.push_str(" - ");
+    search_str.push_str(name);
+    search_str
+}
 
-    Chat(String),
+fn spotify_track_source(id: Option<&rspotify::model::TrackId>, artists: &[rspotify::model::SimplifiedArtist], name: &str) -> Source {
+    match id {
+        Some(id) => Source::Chat(id.url()),
+        None => Source::Chat(spotify_search_query(artists, name)),
+    }
+}
 
-    #[cfg(feature = "rvc")]
-    RVC(rvc::RVCSong),
+/// Songs expanded from a single link to a collection, such as a playlist or an album.
+pub struct Playlist {
+    pub title: String,
+    pub sources: Vec<Source>,
+    pub total: usize,
 }
 
-async fn youtubedl_get_title_async(mut youtubedl: songbird::input::YoutubeDl, optional_artist: Option<String>) -> Option<String> {
-    if let Ok(metadata) = youtubedl.aux_metadata().await {
-        if let Some(artist) = optional_artist.as_ref().or(metadata.artist.as_ref()) {
-            if let Some(title) = metadata.title.as_ref() {
-                return Some(format!("{} - {}", artist, title))
-            }
+async fn report_progress(ctx: &serenity::Context, progress_message: &mut Option<&mut serenity::Message>, title: &str, num_sources: usize) {
+    if let Some(message) = progress_message.as_deref_mut() {
+        let content = format!("{} : loading {} songs...", title, num_sources);
+        message.edit(ctx, serenity::EditMessage::new().content(content)).await.ok();
+    }
+}
+
+#[derive(Deserialize)]
+struct YoutubePlaylistEntry {
+    id: Option<String>,
+    url: Option<String>,
+    playlist_title: Option<String>,
+    playlist_count: Option<usize>,
+}
+
+async fn youtube_playlist(
+    ctx: &serenity::Context,
+    url: &str,
+    limit: usize,
+    mut progress_message: Option<&mut serenity::Message>,
+) -> Result<Playlist, Error> {
+    const PROGRESS_INTERVAL: usize = 10;
+
+    let mut child = tokio::process::Command::new("yt-dlp")
+        .arg("--flat-playlist")
+        .arg("--yes-playlist")
+        .arg("-j")
+        .arg("--playlist-end")
+        .arg(limit.to_string())
+        .arg(url)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child.stdout.take().ok_or(Error::from("Cannot read yt-dlp output"))?;
+    let mut lines = tokio::io::BufReader::new(stdout).lines();
+
+    let mut title = None;
+    let mut total = None;
+    let mut sources = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        let entry: YoutubePlaylistEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        if title.is_none() {
+            title = entry.playlist_title;
         }
-        if let Some(title) = metadata.title.as_ref() {
-            return Some(title.clone())
+        if total.is_none() {
+            total = entry.playlist_count;
+        }
+
+        let entry_url = match (entry.url, entry.id) {
+            (Some(url), _) => url,
+            (None, Some(id)) => format!("https://www.youtube.com/watch?v={}", id),
+            (None, None) => continue,
+        };
+        sources.push(Source::Chat(entry_url));
+
+        if sources.len() % PROGRESS_INTERVAL == 0 {
+            report_progress(ctx, &mut progress_message, title.as_deref().unwrap_or(url), sources.len()).await;
         }
     }
-    None
+    child.wait().await?;
+
+    sources.truncate(limit);
+    let total = total.unwrap_or(sources.len()).max(sources.len());
+
+    Ok(Playlist {
+        title: title.unwrap_or_else(|| url.to_string()),
+        sources,
+        total,
+    })
 }
 
 pub enum InputResult{
@@ -71,10 +245,38 @@ pub enum InputResult{
 impl Source {
     pub async fn get_input(&self, ctx: &serenity::Context, #[allow(unused_variables)] locale: Option<&str>) -> Result<InputResult, Error> {
         match self {
-            Self::Chat(_) => {
-                let source = self.get_youtube(ctx).await?;
-                let title = youtubedl_get_title_async(source.clone(), None);
-                Ok(InputResult::Input(source.into(), Box::pin(title)))
+            Self::Chat(str) => {
+                let shared = data::Shared::get(ctx).await;
+                let link = str.trim().to_string();
+                match provider::find(&shared.providers, &link) {
+                    Some(provider) => {
+                        let (input, metadata) = provider.input(&link).await?;
+                        let shared = shared.clone();
+                        let title = async move {
+                            let metadata = match metadata {
+                                Some(metadata) => metadata,
+                                None => provider::find(&shared.providers, &link)?.aux_metadata(&link).await.ok()?,
+                            };
+                            metadata_title(&metadata)
+                        };
+                        Ok(InputResult::Input(input, Box::pin(title)))
+                    },
+                    None => {
+                        let source = songbird::input::YoutubeDl::new_search(shared.http_client.clone(), str.clone());
+                        let title = youtubedl_get_title_async(source.clone(), None);
+                        Ok(InputResult::Input(source.into(), Box::pin(title)))
+                    },
+                }
+            },
+            Self::Attachment(attachment) => {
+                let http_client = data::Shared::get(ctx).await.http_client.clone();
+                let file = attachment.download(&http_client).await?;
+                let title = attachment.title(&http_client).await;
+                Ok(InputResult::Input(songbird::input::File::new(file).into(), Box::pin(async move { title })))
+            },
+            Self::Library(track) => {
+                let title = track.title();
+                Ok(InputResult::Input(songbird::input::File::new(track.file.clone()).into(), Box::pin(async move { Some(title) })))
             },
             #[cfg(feature = "rvc")]
             Self::RVC(rvc_song) => {
@@ -104,52 +306,585 @@ impl Source {
         }
     }
 
-    pub async fn get_youtube(&self, ctx: &serenity::Context) -> Result<songbird::input::YoutubeDl, Error> {
-        let shared = data::Shared::get(ctx).await;
+    /// Url or path of the audio which ffmpeg can read.
+    async fn media_location(&self, ctx: &serenity::Context) -> Result<String, Error> {
         match self {
             Self::Chat(str) => {
-                match SongLinkType::new(str) {
-                    SongLinkType::Youtube => {
-                        Ok(songbird::input::YoutubeDl::new(shared.http_client.clone(), str.clone()))
-                    },
-                    SongLinkType::Spotify(track_id) => {
-                        let track_id = rspotify::model::TrackId::from_id(&track_id)?;
-                        let track = loop {
-                            match shared.spotify.track(track_id.clone(), None).await {
-                                Ok(track) => break track,
-                                Err(rspotify::ClientError::InvalidToken) => {
-                                    shared.spotify.request_token().await?;
-                                }
-                                Err(err) => {
-                                    return Err(Box::new(err));
-                                }
-                            }
-                        };
-                        let search_str = {
-                            let mut search_str: String = String::with_capacity(64);
-                            search_str.push_str("music ");
-                            search_str.push_str(&track.artists.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>().join(", "));
-                            search_str.push_str(" - ");
-                            search_str.push_str(&track.name);
-                            search_str
-                        };
-
-                        Ok(songbird::input::YoutubeDl::new_search(shared.http_client.clone(), search_str))
-                    },
-                    SongLinkType::Search => {
-                        Ok(songbird::input::YoutubeDl::new_search(shared.http_client.clone(), str.clone()))
+                let shared = data::Shared::get(ctx).await;
+                match provider::find(&shared.providers, str) {
+                    Some(provider) => provider.media_location(str.trim()).await,
+                    None => {
+                        let url = youtube_dl(ctx, str)
+                            .await?
+                            .aux_metadata()
+                            .await?
+                            .source_url
+                            .ok_or(Error::from("Cannot find the song url."))?;
+                        provider::ytdlp_media_location(&url).await
                     },
                 }
             },
+            Self::Attachment(attachment) => {
+                let http_client = data::Shared::get(ctx).await.http_client.clone();
+                Ok(attachment.download(&http_client).await?.to_string_lossy().into_owned())
+            },
+            Self::Library(track) => Ok(track.file.to_string_lossy().into_owned()),
             #[cfg(feature = "rvc")]
-            Self::RVC(rvc_song) => {
-                Ok(rvc_song.youtube.clone())
+            Self::RVC(rvc_song) => Ok(rvc_song.file().to_string_lossy().into_owned()),
+        }
+    }
+
+    pub async fn is_playlist(&self, ctx: &serenity::Context) -> bool {
+        match self {
+            Self::Chat(str) => {
+                let shared = data::Shared::get(ctx).await;
+                provider::find(&shared.providers, str).is_some_and(|provider| provider.is_playlist(str.trim()))
+            },
+            Self::Attachment(_) | Self::Library(_) => false,
+            #[cfg(feature = "rvc")]
+            Self::RVC(_) => false,
+        }
+    }
+
+    pub async fn expand(
+        &self,
+        ctx: &serenity::Context,
+        limit: usize,
+        progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        let str = match self {
+            Self::Chat(str) => str.trim(),
+            Self::Attachment(_) | Self::Library(_) => return Ok(None),
+            #[cfg(feature = "rvc")]
+            Self::RVC(_) => return Ok(None),
+        };
+
+        let shared = data::Shared::get(ctx).await;
+        match provider::find(&shared.providers, str) {
+            Some(provider) if provider.is_playlist(str) => provider.playlist(ctx, str, limit, progress_message).await,
+            _ => Ok(None),
+        }
+    }
+}
+
+/// yt-dlp source of a link or a search query, for features which need the original video such as RVC.
+pub async fn youtube_dl(ctx: &serenity::Context, query: &str) -> Result<songbird::input::YoutubeDl, Error> {
+    let shared = data::Shared::get(ctx).await;
+    match provider::find(&shared.providers, query) {
+        Some(provider) => {
+            let url = provider
+                .aux_metadata(query.trim())
+                .await?
+                .source_url
+                .ok_or(Error::from("Cannot find the song url."))?;
+            Ok(songbird::input::YoutubeDl::new(shared.http_client.clone(), url))
+        },
+        None => Ok(songbird::input::YoutubeDl::new_search(shared.http_client.clone(), query.to_string())),
+    }
+}
+
+fn youtube_video_id(url: &str) -> Option<&str> {
+    if let Some(pos) = url.find("v=") {
+        return url[pos + 2..].split('&').next();
+    }
+    if let Some(pos) = url.find("youtu.be/") {
+        return url[pos + 9..].split('?').next();
+    }
+    None
+}
+
+async fn spotify_recommendation(ctx: &serenity::Context, history: &[HistoryEntry]) -> Result<Option<Source>, Error> {
+    const NUM_SEEDS: usize = 5;
+
+    let shared = data::Shared::get(ctx).await;
+    let spotify = &shared.spotify;
+
+    let mut seed_tracks = Vec::new();
+    for entry in history.iter().take(NUM_SEEDS) {
+        let result = spotify_request(spotify, || {
+            spotify.search(&entry.title, rspotify::model::SearchType::Track, Some(SPOTIFY_MARKET), None, Some(1), None)
+        }).await?;
+        if let rspotify::model::SearchResult::Tracks(page) = result {
+            if let Some(id) = page.items.into_iter().next().and_then(|track| track.id) {
+                seed_tracks.push(id);
             }
         }
     }
+
+    if seed_tracks.is_empty() {
+        return Ok(None);
+    }
+
+    let recommendations = spotify_request(spotify, || {
+        spotify.recommendations(
+            [],
+            None::<Vec<rspotify::model::ArtistId>>,
+            None::<Vec<&str>>,
+            Some(seed_tracks.clone()),
+            Some(SPOTIFY_MARKET),
+            Some(1),
+        )
+    }).await?;
+
+    Ok(recommendations
+        .tracks
+        .first()
+        .map(|track| spotify_track_source(track.id.as_ref(), &track.artists, &track.name)))
 }
 
+async fn youtube_related(ctx: &serenity::Context, history: &[HistoryEntry]) -> Result<Option<Source>, Error> {
+    const NUM_CANDIDATES: usize = 10;
+
+    let played: Vec<&str> = history
+        .iter()
+        .filter_map(|entry| entry.url.as_deref().and_then(youtube_video_id))
+        .collect();
+    let video_id = match played.first() {
+        Some(video_id) => *video_id,
+        None => return Ok(None),
+    };
+
+    // youtube mix of a video lists videos related to it
+    let mix_url = format!("https://www.youtube.com/watch?v={}&list=RD{}", video_id, video_id);
+    let mix = youtube_playlist(ctx, &mix_url, NUM_CANDIDATES, None).await?;
+
+    Ok(mix.sources.into_iter().find(|source| match source {
+        Source::Chat(url) => youtube_video_id(url).is_some_and(|id| !played.contains(&id)),
+        Source::Attachment(_) | Source::Library(_) => false,
+        #[cfg(feature = "rvc")]
+        Source::RVC(_) => false,
+    }))
+}
+
+/// Picks a song related to the history, most recent first.
+pub async fn autoplay_source(ctx: &serenity::Context, history: &[HistoryEntry]) -> Option<Source> {
+    match spotify_recommendation(ctx, history).await {
+        Ok(Some(source)) => return Some(source),
+        Ok(None) => {}
+        Err(err) => tracing::warn!("spotify recommendation failed, e = {}", err),
+    }
+
+    match youtube_related(ctx, history).await {
+        Ok(source) => source,
+        Err(err) => {
+            tracing::warn!("youtube related failed, e = {}", err);
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub playlist_limit: usize,
+    pub volume: u32,
+    pub autoplay: bool,
+    /// Percentage of listeners that must vote to skip a song, or `None` to skip right away.
+    pub vote_skip: Option<u32>,
+    pub dj_role: Option<serenity::RoleId>,
+    /// Overrides of `Action::default_permission`.
+    pub permissions: HashMap<Action, Permission>,
+    /// Limits checked when queueing, `None` for no limit.
+    pub max_queue: Option<usize>,
+    pub max_user_queue: Option<usize>,
+    /// In seconds.
+    pub max_duration: Option<u64>,
+    pub lyrics: bool,
+    /// Seconds the next song overlaps the end of the current one, `None` to not crossfade.
+    pub crossfade: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            playlist_limit: 50,
+            volume: 100,
+            autoplay: false,
+            vote_skip: None,
+            dj_role: None,
+            permissions: HashMap::new(),
+            max_queue: None,
+            max_user_queue: None,
+            max_duration: None,
+            lyrics: false,
+            crossfade: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn permission(&self, action: Action) -> Permission {
+        self.permissions
+            .get(&action)
+            .copied()
+            .unwrap_or(action.default_permission())
+    }
+
+    /// How a skip of the current song by a user goes, `None` if they may not skip it.
+    /// The requester and DJs skip right away.
+    pub fn skip_mode(&self, is_requester: bool, is_dj: bool) -> Option<SkipMode> {
+        if !self.permission(Action::Next).allows(is_requester, is_dj) {
+            return None;
+        }
+        match self.vote_skip {
+            Some(percent) if !is_requester && !is_dj => Some(SkipMode::Vote(percent)),
+            _ => Some(SkipMode::Now),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SkipMode {
+    Now,
+    /// Skipped once this percentage of listeners voted.
+    Vote(u32),
+}
+
+/// Playback actions that can be restricted per guild.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum Action {
+    Join,
+    Leave,
+    Stop,
+    Next,
+    Pause,
+    Seek,
+    Volume,
+    Repeat,
+    Shuffle,
+    Move,
+    Remove,
+    SkipTo,
+    Autoplay,
+    Filter,
+}
+
+impl Action {
+    pub fn from_command(name: &str) -> Option<Self> {
+        match name {
+            "join" => Some(Action::Join),
+            "leave" => Some(Action::Leave),
+            "stop" => Some(Action::Stop),
+            "next" => Some(Action::Next),
+            "pause" | "resume" => Some(Action::Pause),
+            "seek" => Some(Action::Seek),
+            "volume" => Some(Action::Volume),
+            "repeat" => Some(Action::Repeat),
+            "shuffle" => Some(Action::Shuffle),
+            "move" => Some(Action::Move),
+            "remove" => Some(Action::Remove),
+            "skipto" => Some(Action::SkipTo),
+            "autoplay" => Some(Action::Autoplay),
+            "filter" => Some(Action::Filter),
+            _ => None,
+        }
+    }
+
+    pub fn default_permission(&self) -> Permission {
+        match self {
+            Action::Join | Action::Next | Action::Pause | Action::Volume | Action::Repeat | Action::Filter => {
+                Permission::Everyone
+            }
+            Action::Seek | Action::Remove | Action::SkipTo => Permission::Requester,
+            Action::Leave | Action::Stop | Action::Shuffle | Action::Move | Action::Autoplay => Permission::Dj,
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Join => f.write_str("join"),
+            Action::Leave => f.write_str("leave"),
+            Action::Stop => f.write_str("stop"),
+            Action::Next => f.write_str("next"),
+            Action::Pause => f.write_str("pause"),
+            Action::Seek => f.write_str("seek"),
+            Action::Volume => f.write_str("volume"),
+            Action::Repeat => f.write_str("repeat"),
+            Action::Shuffle => f.write_str("shuffle"),
+            Action::Move => f.write_str("move"),
+            Action::Remove => f.write_str("remove"),
+            Action::SkipTo => f.write_str("skipto"),
+            Action::Autoplay => f.write_str("autoplay"),
+            Action::Filter => f.write_str("filter"),
+        }
+    }
+}
+
+/// Who is allowed to perform an `Action`.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum Permission {
+    Everyone,
+    /// Only when every song affected by the action was requested by the user.
+    Requester,
+    Dj,
+}
+
+impl Permission {
+    /// Whether a user is allowed, given whether they requested every affected song and whether they are a DJ.
+    pub fn allows(self, is_requester: bool, is_dj: bool) -> bool {
+        match self {
+            Permission::Everyone => true,
+            Permission::Requester => is_requester || is_dj,
+            Permission::Dj => is_dj,
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Everyone => f.write_str("everyone"),
+            Permission::Requester => f.write_str("requester"),
+            Permission::Dj => f.write_str("dj"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    Track,
+    Queue,
+}
+
+impl RepeatMode {
+    pub fn emoji(&self) -> Option<char> {
+        match self {
+            RepeatMode::Off => None,
+            RepeatMode::Track => Some('🔂'),
+            RepeatMode::Queue => Some('🔁'),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::Track,
+            RepeatMode::Track => RepeatMode::Queue,
+            RepeatMode::Queue => RepeatMode::Off,
+        }
+    }
+}
+
+impl std::fmt::Display for RepeatMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepeatMode::Off => f.write_str("off"),
+            RepeatMode::Track => f.write_str("track"),
+            RepeatMode::Queue => f.write_str("queue"),
+        }
+    }
+}
+
+/// Policy picking which request in the queue plays next.
+/// Requests from users always go before the ones picked by autoplay.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum Scheduler {
+    /// The request with the most queue emoji reactions, the earliest on ties.
+    #[default]
+    Priority,
+    /// The earliest request.
+    Fifo,
+    /// The earliest request of the user who has waited the longest since their last song.
+    RoundRobin,
+}
+
+impl Scheduler {
+    /// Picks the index of the request to play next.
+    /// `recent_authors` are the requesters of the played songs, the most recent first.
+    pub fn select(
+        &self,
+        queue: &[std::sync::Arc<Request>],
+        recent_authors: &[serenity::UserId],
+    ) -> Option<usize> {
+        // lower goes first
+        let rank = |index: usize| -> (bool, usize, usize) {
+            let request = &queue[index];
+            let order = match self {
+                Scheduler::Priority => usize::MAX - request.priority(),
+                Scheduler::Fifo => 0,
+                Scheduler::RoundRobin => {
+                    // users who have not played recently have waited the longest
+                    let waited = recent_authors
+                        .iter()
+                        .position(|author_id| *author_id == request.author_id)
+                        .unwrap_or(usize::MAX);
+                    usize::MAX - waited
+                }
+            };
+            (request.autoplay, order, index)
+        };
+
+        (0..queue.len()).min_by_key(|index| rank(*index))
+    }
+}
+
+impl std::fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scheduler::Priority => f.write_str("priority"),
+            Scheduler::Fifo => f.write_str("fifo"),
+            Scheduler::RoundRobin => f.write_str("round robin"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum FilterPreset {
+    #[default]
+    Off,
+    BassBoost,
+    Nightcore,
+    Vaporwave,
+    #[name = "8D"]
+    EightD,
+    Custom,
+}
+
+/// Audio effect applied through ffmpeg to the current and the next songs.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    pub preset: FilterPreset,
+    /// Playback speed of `FilterPreset::Custom`.
+    pub tempo: f64,
+    /// Semitones of `FilterPreset::Custom`.
+    pub pitch: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            preset: FilterPreset::Off,
+            tempo: 1.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl Filter {
+    const SAMPLE_RATE: u32 = 48000;
+
+    pub fn is_off(&self) -> bool {
+        self.preset == FilterPreset::Off
+    }
+
+    /// How much faster than the original the song plays.
+    pub fn speed(&self) -> f64 {
+        match self.preset {
+            FilterPreset::Nightcore => 1.25,
+            FilterPreset::Vaporwave => 0.8,
+            FilterPreset::Custom => self.tempo,
+            _ => 1.0,
+        }
+    }
+
+    /// Resamples to change the speed and the pitch together, then corrects the speed to `speed`.
+    fn resample(pitch_ratio: f64, speed: f64) -> String {
+        let mut filter = format!(
+            "asetrate={rate}*{pitch_ratio},aresample={rate}",
+            rate = Self::SAMPLE_RATE
+        );
+
+        // atempo only takes 0.5 to 2.0 at once
+        let mut tempo = speed / pitch_ratio;
+        while (tempo - 1.0).abs() > f64::EPSILON {
+            let step = tempo.clamp(0.5, 2.0);
+            filter.push_str(&format!(",atempo={}", step));
+            tempo /= step;
+        }
+        filter
+    }
+
+    /// Argument of ffmpeg `-af`, or `None` if the filter is off.
+    pub fn ffmpeg_filter(&self) -> Option<String> {
+        let filter = match self.preset {
+            FilterPreset::Off => return None,
+            FilterPreset::BassBoost => String::from("bass=g=10:f=110:w=0.6"),
+            FilterPreset::Nightcore => Self::resample(1.25, 1.25),
+            FilterPreset::Vaporwave => Self::resample(0.8, 0.8),
+            FilterPreset::EightD => String::from("apulsator=hz=0.125"),
+            FilterPreset::Custom => Self::resample(2.0f64.powf(self.pitch / 12.0), self.tempo),
+        };
+        Some(format!("aresample={},{}", Self::SAMPLE_RATE, filter))
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.preset {
+            FilterPreset::Off => f.write_str("off"),
+            FilterPreset::BassBoost => f.write_str("bass boost"),
+            FilterPreset::Nightcore => f.write_str("nightcore"),
+            FilterPreset::Vaporwave => f.write_str("vaporwave"),
+            FilterPreset::EightD => f.write_str("8D"),
+            FilterPreset::Custom => write!(f, "tempo x{:.2}, pitch {:+.1}", self.tempo, self.pitch),
+        }
+    }
+}
+
+/// Where in the song a filtered track started, kept in the typemap of the track.
+/// Filtered tracks are streamed through ffmpeg, so their position starts from zero and is scaled by the speed.
 #[derive(Clone, Copy)]
+pub struct TrackOffset {
+    pub start: std::time::Duration,
+    pub speed: f64,
+}
+
+impl serenity::prelude::TypeMapKey for TrackOffset {
+    type Value = TrackOffset;
+}
+
+impl TrackOffset {
+    pub async fn of(track: &songbird::tracks::TrackHandle) -> Option<Self> {
+        track.typemap().read().await.get::<Self>().copied()
+    }
+
+    /// Converts the position of the track to the position in the song.
+    pub async fn song_position(track: &songbird::tracks::TrackHandle, position: std::time::Duration) -> std::time::Duration {
+        match Self::of(track).await {
+            Some(offset) => offset.start + position.mul_f64(offset.speed),
+            None => position,
+        }
+    }
+}
+
+/// Position in the song being played by `track`.
+pub async fn track_position(track: &songbird::tracks::TrackHandle) -> Option<std::time::Duration> {
+    let position = track.get_info().await.ok()?.position;
+    Some(TrackOffset::song_position(track, position).await)
+}
+
+/// Streams `location` through ffmpeg with `filter`, from `start`.
+fn ffmpeg_input(location: &str, filter: &Filter, start: std::time::Duration) -> Result<songbird::input::Input, Error> {
+    let mut ffmpeg = std::process::Command::new("ffmpeg");
+    ffmpeg.args(["-hide_banner", "-loglevel", "error"]);
+    if location.starts_with("http") {
+        ffmpeg.args(["-reconnect", "1", "-reconnect_streamed", "1", "-reconnect_delay_max", "5"]);
+    }
+    if !start.is_zero() {
+        ffmpeg.arg("-ss").arg(format!("{:.3}", start.as_secs_f64()));
+    }
+    ffmpeg.arg("-i").arg(location);
+    if let Some(af) = filter.ffmpeg_filter() {
+        ffmpeg.arg("-af").arg(af);
+    }
+    ffmpeg
+        .args(["-f", "f32le", "-ac", "2", "-ar"])
+        .arg(Filter::SAMPLE_RATE.to_string())
+        .arg("pipe:1")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null());
+
+    let child = songbird::input::ChildContainer::from(ffmpeg.spawn()?);
+    let source = symphonia::core::io::ReadOnlySource::new(child);
+    Ok(songbird::input::RawAdapter::new(source, Filter::SAMPLE_RATE, 2).into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RequestState {
     None,
     Queue,
@@ -172,6 +907,287 @@ impl RequestState {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub title: String,
+    pub url: Option<String>,
+    pub author_id: serenity::UserId,
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub state: RequestState,
+    /// Path of a library track, as in `SavedRequest`.
+    #[serde(default)]
+    pub library: Option<std::path::PathBuf>,
+}
+
+impl HistoryEntry {
+    pub fn new(request: &Request, state: RequestState) -> Self {
+        let metadata = request.metadata.lock().unwrap().clone().unwrap_or_default();
+        let library = match &request.source {
+            Source::Library(track) => Some(track.path.clone()),
+            _ => None,
+        };
+        Self {
+            title: metadata.title.unwrap_or_else(|| request.source.to_string()),
+            url: metadata.source_url,
+            library,
+            author_id: request.author_id,
+            time: chrono::Utc::now(),
+            state,
+        }
+    }
+
+    /// Text to request this song again.
+    pub fn query(&self) -> String {
+        self.url.clone().unwrap_or_else(|| self.title.clone())
+    }
+
+    /// Source to request this song again, `None` if it was a library track which is gone.
+    pub fn source(&self, library: Option<&library::Library>) -> Option<Source> {
+        match &self.library {
+            Some(path) => Some(Source::Library(library?.get(path)?)),
+            None => Some(Source::Chat(self.query())),
+        }
+    }
+}
+
+/// Input of a request resolved in advance, while the song before it is playing.
+/// The work is aborted when dropped without being used.
+pub struct Prefetch {
+    pub request: std::sync::Arc<Request>,
+    /// Filter the input is streamed through, from the start of the song.
+    filter: Filter,
+    task: Option<tokio::task::JoinHandle<Result<InputResult, Error>>>,
+}
+
+impl Prefetch {
+    pub fn new(ctx: &serenity::Context, request: std::sync::Arc<Request>, filter: Filter) -> Self {
+        let ctx = ctx.clone();
+        let task_request = request.clone();
+        let task = tokio::spawn(async move {
+            let request = task_request;
+            let input_result = if filter.is_off() {
+                request.get_input(&ctx).await?
+            } else {
+                request.get_filtered_input(&ctx, &filter, std::time::Duration::ZERO).await?
+            };
+            let (input, title_future) = match input_result {
+                InputResult::Input(input, title_future) => (input, title_future),
+                InputResult::Canceled => return Ok(InputResult::Canceled),
+            };
+
+            let input = input
+                .make_playable_async(
+                    &songbird::input::codecs::CODEC_REGISTRY,
+                    &songbird::input::codecs::PROBE,
+                )
+                .await?;
+            let title = title_future.await;
+            request.aux_metadata(&ctx).await;
+
+            Ok(InputResult::Input(input, Box::pin(futures::future::ready(title))))
+        });
+
+        Self {
+            request,
+            filter,
+            task: Some(task),
+        }
+    }
+
+    pub fn is_for(&self, request: &std::sync::Arc<Request>, filter: &Filter) -> bool {
+        std::sync::Arc::ptr_eq(&self.request, request) && self.filter == *filter
+    }
+
+    /// Whether the input has been resolved, or failed to.
+    pub fn is_ready(&self) -> bool {
+        self.task.as_ref().is_none_or(|task| task.is_finished())
+    }
+
+    pub async fn input(mut self) -> Result<InputResult, Error> {
+        match self.task.take() {
+            Some(task) => task.await?,
+            None => Err(Error::from("Prefetch already used")),
+        }
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// A request stored in the queue file, to be restored after restart.
+#[derive(Serialize, Deserialize)]
+pub struct SavedRequest {
+    pub query: String,
+    pub author_id: serenity::UserId,
+    pub channel_id: serenity::ChannelId,
+    pub message_id: serenity::MessageId,
+    pub locale: Option<String>,
+    pub autoplay: bool,
+    /// Path of a library track. `query` is its title, shown only.
+    #[serde(default)]
+    pub library: Option<std::path::PathBuf>,
+}
+
+impl SavedRequest {
+    /// `None` if the source cannot be restored.
+    pub fn new(request: &Request) -> Option<Self> {
+        let (query, library) = match &request.source {
+            Source::Chat(str) => (str.clone(), None),
+            // the attachment url expires
+            Source::Attachment(_) => return None,
+            Source::Library(track) => (track.title(), Some(track.path.clone())),
+            #[cfg(feature = "rvc")]
+            Source::RVC(_) => return None,
+        };
+
+        Some(Self {
+            query,
+            library,
+            author_id: request.author_id,
+            channel_id: request.channel_id,
+            message_id: request.message_id,
+            locale: request.locale.clone(),
+            autoplay: request.autoplay,
+        })
+    }
+
+    /// `None` if it was a library track which is gone. The library must have been scanned.
+    pub fn restore(self, guild_id: serenity::GuildId, library: Option<&library::Library>) -> Option<Request> {
+        let source = match &self.library {
+            Some(path) => Source::Library(library?.get(path)?),
+            None => Source::Chat(self.query),
+        };
+
+        let request = Request::new(
+            source,
+            guild_id,
+            self.author_id,
+            self.channel_id,
+            self.message_id,
+            self.locale,
+        );
+        Some(if self.autoplay { request.autoplay() } else { request })
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SavedQueue {
+    pub voice_channel: Option<serenity::ChannelId>,
+    pub now: Option<SavedRequest>,
+    pub position: Option<std::time::Duration>,
+    pub queue: Vec<SavedRequest>,
+}
+
+impl SavedQueue {
+    pub fn is_empty(&self) -> bool {
+        self.now.is_none() && self.queue.is_empty()
+    }
+}
+
+/// Queue restored from [`SavedQueue`], waiting for someone to resume or discard it.
+pub struct RestoredQueue {
+    pub voice_channel: Option<serenity::ChannelId>,
+    pub now: Option<std::sync::Arc<Request>>,
+    pub position: Option<std::time::Duration>,
+    pub queue: Vec<std::sync::Arc<Request>>,
+}
+
+impl RestoredQueue {
+    pub fn requests(&self) -> impl Iterator<Item = &std::sync::Arc<Request>> {
+        self.now.iter().chain(self.queue.iter())
+    }
+}
+
+/// Only the bot's own reaction is removed, so the queue emoji of voters stays.
+async fn remove_react(
+    ctx: &serenity::Context,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    state: RequestState,
+) -> Result<(), serenity::Error> {
+    if let Some(emoji) = state.emoji() {
+        ctx.http.delete_reaction_me(channel_id, message_id, &emoji.into()).await?;
+    }
+    Ok(())
+}
+
+async fn add_react(
+    ctx: &serenity::Context,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    state: RequestState,
+) -> Result<(), serenity::Error> {
+    if let Some(emoji) = state.emoji() {
+        ctx.http.create_reaction(channel_id, message_id, &emoji.into()).await?;
+    }
+    Ok(())
+}
+
+/// Requests expanded from one message, such as a playlist.
+/// The message shows their combined state, since reactions cannot be told apart per request.
+pub struct RequestGroup {
+    states: std::sync::Mutex<Vec<std::sync::Arc<std::sync::Mutex<RequestState>>>>,
+    /// State shown by the reactions, locked while they are changed.
+    shown: tokio::sync::Mutex<RequestState>,
+}
+
+impl RequestGroup {
+    pub fn new() -> std::sync::Arc<Self> {
+        std::sync::Arc::new(Self {
+            states: std::sync::Mutex::new(Vec::new()),
+            shown: tokio::sync::Mutex::new(RequestState::None),
+        })
+    }
+
+    /// Playing while any request is playing, queued while any is waiting,
+    /// and once all are finished, their state if they agree or done otherwise.
+    fn state(&self) -> RequestState {
+        let states: Vec<RequestState> = self
+            .states
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|state| *state.lock().unwrap())
+            .collect();
+        combined_state(&states)
+    }
+
+    async fn update_react(
+        &self,
+        ctx: &serenity::Context,
+        channel_id: serenity::ChannelId,
+        message_id: serenity::MessageId,
+    ) -> Result<(), serenity::Error> {
+        let mut shown = self.shown.lock().await;
+        let state = self.state();
+        if *shown != state {
+            remove_react(ctx, channel_id, message_id, *shown).await?;
+            *shown = state;
+            add_react(ctx, channel_id, message_id, state).await?;
+        }
+        Ok(())
+    }
+}
+
+fn combined_state(states: &[RequestState]) -> RequestState {
+    if states.contains(&RequestState::Playing) {
+        return RequestState::Playing;
+    }
+    if states.contains(&RequestState::Queue) {
+        return RequestState::Queue;
+    }
+    match states.first() {
+        Some(first) if states.iter().all(|state| state == first) => *first,
+        Some(_) => RequestState::Done,
+        None => RequestState::None,
+    }
+}
+
 pub struct Request {
     pub source: Source,
     pub guild_id: serenity::GuildId,
@@ -180,6 +1196,15 @@ pub struct Request {
     pub message_id: serenity::MessageId,
     pub locale: Option<String>,
     pub state: std::sync::Arc<std::sync::Mutex<RequestState>>,
+    pub metadata: std::sync::Mutex<Option<songbird::input::AuxMetadata>>,
+    pub autoplay: bool,
+    /// Users, except bots, who reacted with the queue emoji to the request message.
+    /// Kept up to date from reaction events.
+    pub voters: std::sync::Mutex<HashSet<serenity::UserId>>,
+    /// Title announced by a radio stream while it is playing.
+    pub live_title: std::sync::Arc<stream::LiveTitle>,
+    /// Requests sharing the message with this one, if it was expanded from a playlist.
+    pub group: Option<std::sync::Arc<RequestGroup>>,
 }
 
 impl Request {
@@ -199,49 +1224,176 @@ impl Request {
             channel_id,
             message_id,
             locale: locale.map(Into::into),
-            state: std::sync::Arc::new(std::sync::Mutex::new(RequestState::Queue))
+            state: std::sync::Arc::new(std::sync::Mutex::new(RequestState::Queue)),
+            metadata: std::sync::Mutex::new(None),
+            autoplay: false,
+            voters: std::sync::Mutex::new(HashSet::new()),
+            live_title: Default::default(),
+            group: None,
         }
     }
 
+    /// Marks the request as picked by autoplay, not by a user.
+    pub fn autoplay(mut self) -> Self {
+        self.autoplay = true;
+        self
+    }
+
+    /// Adds the request to `group`, whose reactions it shares.
+    pub fn in_group(mut self, group: &std::sync::Arc<RequestGroup>) -> Self {
+        group.states.lock().unwrap().push(self.state.clone());
+        self.group = Some(group.clone());
+        self
+    }
+
+    pub fn with_source(&self, source: Source) -> Self {
+        Self::new(
+            source,
+            self.guild_id,
+            self.author_id,
+            self.channel_id,
+            self.message_id,
+            self.locale.as_ref(),
+        )
+    }
+
     pub fn cancel(&self) {
         if let Source::RVC(song) = &self.source {
             song.cancel();
         }
     }
 
-    pub async fn messge(&self, ctx: &serenity::Context) -> Option<serenity::Message> {
-        ctx.http.get_message(self.channel_id, self.message_id).await.ok()
+    /// Same as `Source::get_input`, but radio streams are played from the connection their titles are read from.
+    pub async fn get_input(&self, ctx: &serenity::Context) -> Result<InputResult, Error> {
+        self.live_title.reset();
+        if let Source::Chat(str) = &self.source {
+            let link = str.trim();
+            let shared = data::Shared::get(ctx).await;
+            if provider::find(&shared.providers, link).is_some_and(|provider| provider.is_live(link)) {
+                if let Some((input, metadata)) = stream::open(&shared.http_client, link, &self.live_title).await? {
+                    let title = metadata_title(&metadata);
+                    self.metadata.lock().unwrap().get_or_insert(metadata);
+                    return Ok(InputResult::Input(input, Box::pin(futures::future::ready(title))));
+                }
+            }
+        }
+        self.source.get_input(ctx, self.locale.as_deref()).await
     }
-    
-    pub async fn remove_react(&self, ctx: &serenity::Context) -> Result<(), serenity::Error> {
-        let emoji = self.state.lock().unwrap().emoji();
-        if let Some(emoji) = emoji {
-            ctx.http
-            .delete_message_reaction_emoji(
-                self.channel_id,
-                self.message_id,
-                &emoji.into(),
-            )
-            .await?;
+
+    /// Same as `Source::get_input`, but streamed through ffmpeg with `filter` from `start`.
+    pub async fn get_filtered_input(
+        &self,
+        ctx: &serenity::Context,
+        filter: &Filter,
+        start: std::time::Duration,
+    ) -> Result<InputResult, Error> {
+        self.live_title.reset();
+        let (location, title) = match &self.source {
+            // the metadata of the request is looked up once, and gives both the page of the song and its title
+            Source::Chat(_) => {
+                let metadata = self.aux_metadata(ctx).await;
+                let location = match metadata.as_ref().and_then(|metadata| metadata.source_url.as_deref()) {
+                    Some(url) => {
+                        let shared = data::Shared::get(ctx).await;
+                        match provider::find(&shared.providers, url) {
+                            Some(provider) => provider.media_location(url).await?,
+                            None => provider::ytdlp_media_location(url).await?,
+                        }
+                    }
+                    None => self.source.media_location(ctx).await?,
+                };
+                (location, metadata.as_ref().and_then(metadata_title))
+            }
+            _ => {
+                let title_future = match self.source.get_input(ctx, self.locale.as_deref()).await? {
+                    InputResult::Input(_, title_future) => title_future,
+                    InputResult::Canceled => return Ok(InputResult::Canceled),
+                };
+                (self.source.media_location(ctx).await?, title_future.await)
+            }
+        };
+
+        Ok(InputResult::Input(ffmpeg_input(&location, filter, start)?, Box::pin(futures::future::ready(title))))
+    }
+
+    pub async fn aux_metadata(&self, ctx: &serenity::Context) -> Option<songbird::input::AuxMetadata> {
+        if let Some(metadata) = self.metadata.lock().unwrap().clone() {
+            return Some(metadata);
         }
-        Ok(())
+
+        let metadata = match &self.source {
+            Source::Chat(str) => {
+                let shared = data::Shared::get(ctx).await;
+                match provider::find(&shared.providers, str) {
+                    Some(provider) => provider.aux_metadata(str.trim()).await.ok()?,
+                    None => youtube_dl(ctx, str).await.ok()?.aux_metadata().await.ok()?,
+                }
+            },
+            Source::Attachment(attachment) => {
+                let http_client = data::Shared::get(ctx).await.http_client.clone();
+                attachment.aux_metadata(&http_client).await.ok()?
+            },
+            Source::Library(track) => track.aux_metadata(),
+            #[cfg(feature = "rvc")]
+            Source::RVC(rvc_song) => rvc_song.metadata.clone(),
+        };
+
+        *self.metadata.lock().unwrap() = Some(metadata.clone());
+        Some(metadata)
     }
-    
-    pub async fn add_react(&self, ctx: &serenity::Context) -> Result<(), serenity::Error> {
-        let emoji = self.state.lock().unwrap().emoji();
-        if let Some(emoji) = emoji {
-            ctx.http
-            .create_reaction(self.channel_id, self.message_id, &emoji.into())
-            .await?;
+
+    /// Number of users, except bots, who reacted with the queue emoji to the request message.
+    pub fn priority(&self) -> usize {
+        self.voters.lock().unwrap().len()
+    }
+
+    pub fn vote(&self, user_id: serenity::UserId, added: bool) {
+        let mut voters = self.voters.lock().unwrap();
+        if added {
+            voters.insert(user_id);
+        } else {
+            voters.remove(&user_id);
         }
-        Ok(())
     }
 
+    /// Fetches the users who reacted with the queue emoji, for when reaction events may have been missed.
+    pub async fn fetch_voters(&self, ctx: &serenity::Context) -> Result<HashSet<serenity::UserId>, serenity::Error> {
+        const PAGE_SIZE: u8 = 100;
+
+        let emoji_queue = RequestState::Queue.emoji().unwrap();
+        let mut voters = HashSet::new();
+        let mut after = None;
+        loop {
+            let users = ctx
+                .http
+                .get_reaction_users(self.channel_id, self.message_id, &emoji_queue.into(), PAGE_SIZE, after)
+                .await?;
+            voters.extend(users.iter().filter(|user| !user.bot).map(|user| user.id));
+
+            match users.last() {
+                Some(user) if users.len() == PAGE_SIZE as usize => after = Some(user.id.get()),
+                _ => return Ok(voters),
+            }
+        }
+    }
+
+    pub async fn messge(&self, ctx: &serenity::Context) -> Option<serenity::Message> {
+        ctx.http.get_message(self.channel_id, self.message_id).await.ok()
+    }
+    
     pub async fn set_state_async(&self, ctx: &serenity::Context, state: RequestState) -> Result<(), serenity::Error> {
-        self.remove_react(ctx).await?;
-        *self.state.lock().unwrap() = state;
-        self.add_react(ctx).await?;
-        Ok(())
+        match &self.group {
+            Some(group) => {
+                *self.state.lock().unwrap() = state;
+                group.update_react(ctx, self.channel_id, self.message_id).await
+            }
+            None => {
+                let previous = *self.state.lock().unwrap();
+                remove_react(ctx, self.channel_id, self.message_id, previous).await?;
+                *self.state.lock().unwrap() = state;
+                add_react(ctx, self.channel_id, self.message_id, state).await
+            }
+        }
     }
 
     pub fn set_state_nowait(self: std::sync::Arc<Request>, ctx: serenity::Context, state: RequestState) {
@@ -253,8 +1405,12 @@ impl Request {
 
 impl From<&serenity::Message> for Request {
     fn from(value: &serenity::Message) -> Self {
+        let source = match attachment::Attachment::from_message(value) {
+            Some(attachment) => Source::Attachment(attachment),
+            None => Source::Chat(value.content.clone()),
+        };
         Self::new(
-            Source::Chat(value.content.clone()),
+            source,
             value.guild_id.expect("Except message is in guild"),
             value.author.id,
             value.channel_id,
@@ -271,6 +1427,7 @@ pub enum Now {
     Playing {
         track: songbird::tracks::TrackHandle,
         request: std::sync::Arc<Request>,
+        paused: bool,
     }
 }
 
@@ -280,9 +1437,280 @@ impl Now {
             Self::Waiting { request } => {
                 request.clone()
             },
-            Self::Playing { track: _, request } => {
+            Self::Playing { request, .. } => {
                 request.clone()
             }
         }
     }
+
+    pub fn track(&self) -> Option<songbird::tracks::TrackHandle> {
+        match self {
+            Self::Waiting { .. } => None,
+            Self::Playing { track, .. } => Some(track.clone()),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        match self {
+            Self::Waiting { .. } => false,
+            Self::Playing { paused, .. } => *paused,
+        }
+    }
+
+    pub fn set_paused(&mut self, new_paused: bool) -> Result<(), Error> {
+        match self {
+            Self::Waiting { .. } => Err(Error::from("Song is not playing yet.")),
+            Self::Playing { track, paused, .. } => {
+                if new_paused {
+                    track.pause()?;
+                } else {
+                    track.play()?;
+                }
+                *paused = new_paused;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn format_duration_adds_hours_when_needed() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0:00");
+        assert_eq!(format_duration(Duration::from_secs(83)), "1:23");
+        assert_eq!(format_duration(Duration::from_secs(3600 + 2 * 60 + 3)), "1:02:03");
+    }
+
+    #[test]
+    fn parse_duration_formats() {
+        assert_eq!(parse_duration("42"), Some(Duration::from_secs(42)));
+        assert_eq!(parse_duration(" 1:23 "), Some(Duration::from_secs(83)));
+        assert_eq!(parse_duration("1:02:03"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("1:2:3:4"), None);
+        assert_eq!(parse_duration("1:xx"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn parse_duration_overflow() {
+        assert_eq!(parse_duration(&format!("{}:00", u64::MAX)), None);
+        assert_eq!(parse_duration(&format!("1:{}", u64::MAX)), None);
+    }
+
+    fn request(author: u64, votes: &[u64]) -> std::sync::Arc<Request> {
+        let request = Request::new(
+            Source::Chat(format!("song of {}", author)),
+            serenity::GuildId::new(1),
+            serenity::UserId::new(author),
+            serenity::ChannelId::new(1),
+            serenity::MessageId::new(1),
+            None::<String>,
+        );
+        for voter in votes {
+            request.vote(serenity::UserId::new(*voter), true);
+        }
+        std::sync::Arc::new(request)
+    }
+
+    fn autoplay_request(author: u64) -> std::sync::Arc<Request> {
+        let request = std::sync::Arc::into_inner(request(author, &[])).unwrap();
+        std::sync::Arc::new(request.autoplay())
+    }
+
+    #[test]
+    fn history_library_track_is_not_searched() {
+        let entry = |library: Option<&str>| HistoryEntry {
+            title: String::from("Artist - Song"),
+            url: None,
+            author_id: serenity::UserId::new(1),
+            time: chrono::Utc::now(),
+            state: RequestState::Done,
+            library: library.map(Into::into),
+        };
+        let library = library::Library::new(std::env::temp_dir().join("ui-chan-missing-library"));
+
+        assert!(entry(Some("Artist - Song.mp3")).source(Some(&library)).is_none());
+        assert!(entry(Some("Artist - Song.mp3")).source(None).is_none());
+        let source = entry(None).source(Some(&library));
+        assert!(matches!(source, Some(Source::Chat(ref query)) if query == "Artist - Song"));
+    }
+
+    #[test]
+    fn skip_mode_follows_permission() {
+        let mut config = Config::default();
+        assert_eq!(config.skip_mode(false, false), Some(SkipMode::Now));
+
+        config.vote_skip = Some(50);
+        assert_eq!(config.skip_mode(false, false), Some(SkipMode::Vote(50)));
+        assert_eq!(config.skip_mode(true, false), Some(SkipMode::Now));
+        assert_eq!(config.skip_mode(false, true), Some(SkipMode::Now));
+
+        // a skip reaction by the requester or a listener is ignored when only DJs may skip
+        config.permissions.insert(Action::Next, Permission::Dj);
+        assert_eq!(config.skip_mode(false, false), None);
+        assert_eq!(config.skip_mode(true, false), None);
+        assert_eq!(config.skip_mode(false, true), Some(SkipMode::Now));
+        config.vote_skip = None;
+        assert_eq!(config.skip_mode(true, false), None);
+        assert_eq!(config.skip_mode(false, true), Some(SkipMode::Now));
+    }
+
+    #[test]
+    fn saved_library_track_is_not_searched() {
+        let saved = |library: Option<&str>| SavedRequest {
+            query: String::from("Artist - Song"),
+            author_id: serenity::UserId::new(1),
+            channel_id: serenity::ChannelId::new(1),
+            message_id: serenity::MessageId::new(1),
+            locale: None,
+            autoplay: false,
+            library: library.map(Into::into),
+        };
+        let guild_id = serenity::GuildId::new(1);
+        let library = library::Library::new(std::env::temp_dir().join("ui-chan-missing-library"));
+
+        assert!(saved(Some("Artist - Song.mp3")).restore(guild_id, Some(&library)).is_none());
+        assert!(saved(Some("Artist - Song.mp3")).restore(guild_id, None).is_none());
+        let request = saved(None).restore(guild_id, Some(&library)).unwrap();
+        assert!(matches!(request.source, Source::Chat(ref query) if query == "Artist - Song"));
+    }
+
+    #[test]
+    fn scheduler_empty_queue() {
+        assert_eq!(Scheduler::Fifo.select(&[], &[]), None);
+        assert_eq!(Scheduler::Priority.select(&[], &[]), None);
+        assert_eq!(Scheduler::RoundRobin.select(&[], &[]), None);
+    }
+
+    #[test]
+    fn scheduler_fifo_picks_earliest() {
+        let queue = [request(1, &[]), request(2, &[10, 11]), request(3, &[])];
+        assert_eq!(Scheduler::Fifo.select(&queue, &[]), Some(0));
+    }
+
+    #[test]
+    fn scheduler_priority_picks_most_votes() {
+        let queue = [request(1, &[10]), request(2, &[10, 11]), request(3, &[12])];
+        assert_eq!(Scheduler::Priority.select(&queue, &[]), Some(1));
+    }
+
+    #[test]
+    fn scheduler_priority_ties_go_to_earliest() {
+        let queue = [request(1, &[]), request(2, &[10]), request(3, &[11])];
+        assert_eq!(Scheduler::Priority.select(&queue, &[]), Some(1));
+
+        let queue = [request(1, &[]), request(2, &[])];
+        assert_eq!(Scheduler::Priority.select(&queue, &[]), Some(0));
+    }
+
+    #[test]
+    fn scheduler_user_requests_before_autoplay() {
+        let queue = [autoplay_request(1), request(2, &[])];
+        assert_eq!(Scheduler::Fifo.select(&queue, &[]), Some(1));
+
+        let queue = [request(1, &[]), autoplay_request(2)];
+        assert_eq!(Scheduler::Priority.select(&queue, &[]), Some(0));
+        assert_eq!(Scheduler::RoundRobin.select(&queue, &[serenity::UserId::new(1)]), Some(0));
+    }
+
+    #[test]
+    fn scheduler_round_robin_prefers_who_waited_longest() {
+        let (a, b, c) = (serenity::UserId::new(1), serenity::UserId::new(2), serenity::UserId::new(3));
+        let queue = [request(1, &[]), request(2, &[]), request(3, &[])];
+
+        // users who have never played go first, the earliest on ties
+        assert_eq!(Scheduler::RoundRobin.select(&queue, &[]), Some(0));
+        assert_eq!(Scheduler::RoundRobin.select(&queue, &[a]), Some(1));
+        assert_eq!(Scheduler::RoundRobin.select(&queue, &[b, a, c]), Some(2));
+        assert_eq!(Scheduler::RoundRobin.select(&queue, &[c, b, a]), Some(0));
+    }
+
+    #[test]
+    fn scheduler_round_robin_takes_turns() {
+        let mut queue: Vec<_> = [1, 1, 1, 2, 2].iter().map(|author| request(*author, &[])).collect();
+        let mut recent_authors = Vec::new();
+        let mut played = Vec::new();
+        while let Some(index) = Scheduler::RoundRobin.select(&queue, &recent_authors) {
+            let request = queue.remove(index);
+            recent_authors.insert(0, request.author_id);
+            played.push(request.author_id.get());
+        }
+        assert_eq!(played, [1, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn filter_off_has_no_ffmpeg_filter() {
+        assert_eq!(Filter::default().ffmpeg_filter(), None);
+        assert_eq!(Filter::default().speed(), 1.0);
+    }
+
+    #[test]
+    fn filter_presets() {
+        let preset = |preset| Filter { preset, ..Default::default() };
+        assert_eq!(
+            preset(FilterPreset::BassBoost).ffmpeg_filter().unwrap(),
+            "aresample=48000,bass=g=10:f=110:w=0.6"
+        );
+        // the pitch and the speed change together, so no tempo correction is needed
+        assert_eq!(
+            preset(FilterPreset::Nightcore).ffmpeg_filter().unwrap(),
+            "aresample=48000,asetrate=48000*1.25,aresample=48000"
+        );
+        assert_eq!(preset(FilterPreset::Nightcore).speed(), 1.25);
+        assert_eq!(preset(FilterPreset::Vaporwave).speed(), 0.8);
+    }
+
+    #[test]
+    fn filter_custom_tempo_and_pitch() {
+        let custom = |tempo, pitch| Filter { preset: FilterPreset::Custom, tempo, pitch };
+        assert_eq!(
+            custom(2.0, 0.0).ffmpeg_filter().unwrap(),
+            "aresample=48000,asetrate=48000*1,aresample=48000,atempo=2"
+        );
+        assert_eq!(custom(2.0, 0.0).speed(), 2.0);
+
+        // an octave up at half speed is a quarter tempo, beyond what one atempo takes
+        assert_eq!(
+            custom(0.5, 12.0).ffmpeg_filter().unwrap(),
+            "aresample=48000,asetrate=48000*2,aresample=48000,atempo=0.5,atempo=0.5"
+        );
+    }
+
+    #[test]
+    fn filter_atempo_steps_stay_in_range() {
+        for (tempo, pitch) in [(0.5, 12.0), (2.0, -12.0), (1.3, 5.0), (0.7, -7.0)] {
+            let filter = Filter { preset: FilterPreset::Custom, tempo, pitch };
+            let af = filter.ffmpeg_filter().unwrap();
+            let steps: Vec<f64> = af
+                .split(',')
+                .filter_map(|part| part.strip_prefix("atempo="))
+                .map(|step| step.parse().unwrap())
+                .collect();
+            assert!(steps.iter().all(|step| (0.5..=2.0).contains(step)), "{}", af);
+
+            let pitch_ratio = 2.0f64.powf(pitch / 12.0);
+            let product: f64 = steps.iter().product();
+            assert!((product * pitch_ratio - tempo).abs() < 1e-9, "{}", af);
+        }
+    }
+
+    #[test]
+    fn combined_state_prefers_playing_then_queue() {
+        use RequestState::*;
+        assert_eq!(combined_state(&[Done, Queue, Playing]), Playing);
+        assert_eq!(combined_state(&[Done, Queue, Canceled]), Queue);
+    }
+
+    #[test]
+    fn combined_state_of_finished_requests() {
+        use RequestState::*;
+        assert_eq!(combined_state(&[Canceled, Canceled]), Canceled);
+        assert_eq!(combined_state(&[Skipped]), Skipped);
+        assert_eq!(combined_state(&[Done, Skipped, Canceled]), Done);
+        assert_eq!(combined_state(&[]), None);
+    }
 }
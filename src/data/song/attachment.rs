@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+use crate::prelude::*;
+
+use super::tags::{self, Tags};
+
+/// An audio file attached to a message in the song channel.
+/// It is downloaded to the temp directory when first needed, and removed when dropped.
+pub struct Attachment {
+    pub id: serenity::AttachmentId,
+    pub filename: String,
+    pub url: String,
+    download_lock: serenity::prelude::Mutex<()>,
+}
+
+impl Attachment {
+    /// Larger files are not played, since they are kept on disk while queued.
+    pub const MAX_SIZE: u64 = 100 << 20;
+
+    /// The first playable attachment of `message`.
+    pub fn from_message(message: &serenity::Message) -> Option<Self> {
+        message
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.size as u64 <= Self::MAX_SIZE)
+            .find(|attachment| tags::is_audio_file(Path::new(&attachment.filename)))
+            .map(|attachment| Self {
+                id: attachment.id,
+                filename: attachment.filename.clone(),
+                url: attachment.url.clone(),
+                download_lock: serenity::prelude::Mutex::new(()),
+            })
+    }
+
+    pub fn file(&self) -> PathBuf {
+        let extension = Path::new(&self.filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        Path::new("temp").join("attachment").join(format!("{}.{}", self.id, extension))
+    }
+
+    /// Downloads the attachment, unless it is already downloaded.
+    pub async fn download(&self, http_client: &reqwest::Client) -> Result<PathBuf, Error> {
+        let _lock = self.download_lock.lock().await;
+
+        let file = self.file();
+        if file.exists() {
+            return Ok(file);
+        }
+
+        let mut response = http_client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?;
+        if let Some(dir) = file.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        // written next to the file first, so that an interrupted download is not taken as done
+        let partial = file.with_extension("part");
+        let mut output = tokio::fs::File::create(&partial).await?;
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            size += chunk.len() as u64;
+            if size > Self::MAX_SIZE {
+                drop(output);
+                tokio::fs::remove_file(&partial).await.ok();
+                return Err(Error::from("The attachment is too large."));
+            }
+            output.write_all(&chunk).await?;
+        }
+        output.flush().await?;
+        drop(output);
+
+        tokio::fs::rename(&partial, &file).await?;
+        Ok(file)
+    }
+
+    /// Downloads the attachment and reads its tags. The file name is used if it has no title.
+    pub async fn tags(&self, http_client: &reqwest::Client) -> Result<Tags, Error> {
+        let file = self.download(http_client).await?;
+        let mut tags = tokio::task::spawn_blocking(move || Tags::read(&file)).await??;
+
+        if tags.title.is_none() {
+            let stem = Path::new(&self.filename).file_stem().and_then(|stem| stem.to_str());
+            tags.title = Some(stem.unwrap_or(&self.filename).to_string());
+        }
+        Ok(tags)
+    }
+
+    pub async fn aux_metadata(&self, http_client: &reqwest::Client) -> Result<songbird::input::AuxMetadata, Error> {
+        let mut metadata = self.tags(http_client).await?.aux_metadata();
+        metadata.source_url = Some(self.url.clone());
+        Ok(metadata)
+    }
+
+    pub async fn title(&self, http_client: &reqwest::Client) -> Option<String> {
+        self.tags(http_client).await.ok()?.display_title()
+    }
+}
+
+impl std::fmt::Display for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.filename)
+    }
+}
+
+impl Drop for Attachment {
+    fn drop(&mut self) {
+        std::fs::remove_file(self.file()).ok();
+        std::fs::remove_file(self.file().with_extension("part")).ok();
+    }
+}
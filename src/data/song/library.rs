@@ -0,0 +1,263 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::prelude::*;
+
+use super::tags::{self, Tags};
+
+/// A file in the library.
+pub struct Track {
+    /// Relative to the library root, used as the id of the track.
+    pub path: PathBuf,
+    pub file: PathBuf,
+    pub tags: Tags,
+    modified: SystemTime,
+}
+
+impl Track {
+    /// "artist - title", or the file name if the track has no title.
+    pub fn title(&self) -> String {
+        self.tags.display_title().unwrap_or_else(|| self.file_stem())
+    }
+
+    fn file_stem(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn aux_metadata(&self) -> songbird::input::AuxMetadata {
+        let mut metadata = self.tags.aux_metadata();
+        if metadata.title.is_none() {
+            metadata.title = Some(self.file_stem());
+        }
+        metadata
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let haystack = format!(
+            "{} {} {} {}",
+            self.tags.artist.as_deref().unwrap_or_default(),
+            self.tags.title.as_deref().unwrap_or_default(),
+            self.tags.album.as_deref().unwrap_or_default(),
+            self.path.to_string_lossy(),
+        );
+        matches(&haystack, query)
+    }
+}
+
+impl std::fmt::Display for Track {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.title())
+    }
+}
+
+/// Whether every word of `query` is in `haystack`, ignoring case.
+fn matches(haystack: &str, query: &str) -> bool {
+    let haystack = haystack.to_lowercase();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| haystack.contains(word))
+}
+
+#[derive(Default)]
+pub struct ScanResult {
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Audio files under a directory, indexed by their tags.
+pub struct Library {
+    pub root: PathBuf,
+    tracks: std::sync::RwLock<HashMap<PathBuf, Arc<Track>>>,
+    /// Whether the first scan has finished.
+    scanned: tokio::sync::watch::Sender<bool>,
+}
+
+impl Library {
+    /// Files are polled for changes, since only modified files are read again.
+    pub const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            tracks: std::sync::RwLock::new(HashMap::new()),
+            scanned: tokio::sync::watch::Sender::new(false),
+        }
+    }
+
+    /// Waits for the first scan to finish.
+    pub async fn scanned(&self) {
+        self.scanned.subscribe().wait_for(|scanned| *scanned).await.ok();
+    }
+
+    pub fn num_tracks(&self) -> usize {
+        self.tracks.read().unwrap().len()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Arc<Track>> {
+        self.tracks.read().unwrap().get(path).cloned()
+    }
+
+    /// Reads the tags of new or modified files, and drops deleted ones. This blocks.
+    fn scan(&self) -> ScanResult {
+        // keep the index while the root is unavailable, such as an unmounted drive
+        if !self.root.is_dir() {
+            return ScanResult::default();
+        }
+
+        let mut files = Vec::new();
+        walk(&self.root, &mut files);
+
+        let changed: Vec<_> = {
+            let tracks = self.tracks.read().unwrap();
+            files
+                .iter()
+                .filter(|(file, modified)| {
+                    let path = file.strip_prefix(&self.root).unwrap_or(file);
+                    tracks.get(path).is_none_or(|track| track.modified != *modified)
+                })
+                .cloned()
+                .collect()
+        };
+
+        let updated: Vec<_> = changed
+            .into_iter()
+            .map(|(file, modified)| {
+                let tags = Tags::read(&file).unwrap_or_else(|err| {
+                    tracing::warn!("cannot read tags of {}, e = {}", file.display(), err);
+                    Tags::default()
+                });
+                Track {
+                    path: file.strip_prefix(&self.root).unwrap_or(&file).to_path_buf(),
+                    file,
+                    tags,
+                    modified,
+                }
+            })
+            .collect();
+
+        let existing: HashSet<_> = files
+            .iter()
+            .map(|(file, _)| file.strip_prefix(&self.root).unwrap_or(file).to_path_buf())
+            .collect();
+
+        let mut tracks = self.tracks.write().unwrap();
+        let num_tracks = tracks.len();
+        tracks.retain(|path, _| existing.contains(path));
+        let removed = num_tracks - tracks.len();
+
+        let result = ScanResult {
+            updated: updated.len(),
+            removed,
+        };
+        for track in updated {
+            tracks.insert(track.path.clone(), Arc::new(track));
+        }
+        result
+    }
+
+    pub async fn rescan(self: &Arc<Self>) -> Result<ScanResult, Error> {
+        let library = self.clone();
+        Ok(tokio::task::spawn_blocking(move || library.scan()).await?)
+    }
+
+    /// Scans the library now, and again every `RESCAN_INTERVAL`.
+    pub fn watch(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                match self.rescan().await {
+                    Ok(result) if result.updated > 0 || result.removed > 0 => tracing::info!(
+                        "library scanned, {} updated, {} removed, {} tracks",
+                        result.updated,
+                        result.removed,
+                        self.num_tracks()
+                    ),
+                    Ok(_) => {}
+                    Err(err) => tracing::warn!("library scan failed, e = {}", err),
+                }
+                self.scanned.send_replace(true);
+                tokio::time::sleep(Self::RESCAN_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Tracks matching `query` by artist, title, album or path.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<Arc<Track>> {
+        let mut tracks: Vec<_> = self
+            .tracks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|track| track.matches(query))
+            .cloned()
+            .collect();
+        tracks.sort_by_key(|track| track.title().to_lowercase());
+        tracks.truncate(limit);
+        tracks
+    }
+
+    /// Names of the albums matching `query`.
+    pub fn albums(&self, query: &str, limit: usize) -> Vec<String> {
+        let albums: HashSet<_> = self
+            .tracks
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|track| track.tags.album.clone())
+            .filter(|album| matches(album, query))
+            .collect();
+
+        let mut albums: Vec<_> = albums.into_iter().collect();
+        albums.sort_by_key(|album| album.to_lowercase());
+        albums.truncate(limit);
+        albums
+    }
+
+    /// Tracks of the album, in track number order.
+    pub fn album(&self, name: &str) -> Vec<Arc<Track>> {
+        let mut tracks: Vec<_> = self
+            .tracks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|track| track.tags.album.as_deref() == Some(name))
+            .cloned()
+            .collect();
+        tracks.sort_by(|a, b| {
+            (a.tags.track_number.is_none(), a.tags.track_number, &a.path)
+                .cmp(&(b.tags.track_number.is_none(), b.tags.track_number, &b.path))
+        });
+        tracks
+    }
+}
+
+/// Collects the audio files under `dir` with their modified time.
+fn walk(dir: &Path, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            tracing::warn!("cannot read {}, e = {}", dir.display(), err);
+            return;
+        }
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            walk(&path, files);
+        } else if tags::is_audio_file(&path) {
+            files.push((path, metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+        }
+    }
+}
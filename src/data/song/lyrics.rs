@@ -0,0 +1,313 @@
+use poise::serenity_prelude::async_trait;
+use serde::Deserialize;
+use std::{path::PathBuf, time::Duration};
+
+use crate::prelude::*;
+
+pub struct Line {
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+pub struct Lyrics {
+    pub lines: Vec<Line>,
+}
+
+impl Lyrics {
+    /// Parses LRC text. Lines without a timestamp are kept unsynced.
+    pub fn parse_lrc(lrc: &str) -> Self {
+        let mut lines = Vec::new();
+
+        for raw_line in lrc.lines() {
+            let mut rest = raw_line.trim();
+            let mut times = Vec::new();
+            let mut is_tag = false;
+
+            while let Some(end) = rest.strip_prefix('[').and_then(|tag| tag.find(']')) {
+                let tag = &rest[1..end + 1];
+                match parse_timestamp(tag) {
+                    Some(time) => times.push(time),
+                    // metadata such as [ar:artist]
+                    None => is_tag = true,
+                }
+                rest = rest[end + 2..].trim_start();
+            }
+
+            if is_tag && times.is_empty() {
+                continue;
+            }
+
+            let text = rest.trim().to_string();
+            if times.is_empty() {
+                if !text.is_empty() {
+                    lines.push(Line { time: None, text });
+                }
+            } else {
+                lines.extend(times.into_iter().map(|time| Line {
+                    time: Some(time),
+                    text: text.clone(),
+                }));
+            }
+        }
+
+        lines.sort_by_key(|line| line.time);
+        Self { lines }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.lines.iter().any(|line| line.time.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.text.is_empty())
+    }
+
+    /// Index of the line being sung at `position`.
+    pub fn line_at(&self, position: Duration) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| line.time.is_some_and(|time| time <= position))
+    }
+
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// `mm:ss.xx`
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    Duration::from_secs(minutes.checked_mul(60)?).checked_add(Duration::try_from_secs_f64(seconds).ok()?)
+}
+
+/// Artist and title to look up lyrics with, guessed from "artist - title" if the artist is unknown.
+pub fn search_terms(metadata: &songbird::input::AuxMetadata) -> Option<(String, String)> {
+    let title = metadata.track.as_ref().or(metadata.title.as_ref())?;
+    let title = strip_brackets(title);
+
+    match metadata.artist.as_ref().or(metadata.channel.as_ref()) {
+        Some(artist) if metadata.artist.is_some() || !title.contains(" - ") => {
+            Some((artist.trim_end_matches(" - Topic").to_string(), title))
+        }
+        _ => {
+            let (artist, title) = title.split_once(" - ")?;
+            Some((artist.trim().to_string(), title.trim().to_string()))
+        }
+    }
+}
+
+/// Removes suffixes such as "(Official Video)" or "[MV]".
+fn strip_brackets(title: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = (depth - 1).max(0),
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    result.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[async_trait]
+pub trait Provider: Send + Sync {
+    async fn lookup(&self, artist: &str, title: &str, duration: Option<Duration>) -> Result<Option<Lyrics>, Error>;
+}
+
+/// Tries the providers in order, and returns the first lyrics found.
+pub async fn lookup(
+    providers: &[Box<dyn Provider>],
+    artist: &str,
+    title: &str,
+    duration: Option<Duration>,
+) -> Option<Lyrics> {
+    for provider in providers {
+        match provider.lookup(artist, title, duration).await {
+            Ok(Some(lyrics)) if !lyrics.is_empty() => return Some(lyrics),
+            Ok(_) => {}
+            Err(err) => tracing::warn!("lyrics lookup failed, e = {}", err),
+        }
+    }
+    None
+}
+
+/// Reads `{artist} - {title}.lrc` from a directory.
+pub struct FileProvider {
+    pub dir: PathBuf,
+}
+
+#[async_trait]
+impl Provider for FileProvider {
+    async fn lookup(&self, artist: &str, title: &str, _: Option<Duration>) -> Result<Option<Lyrics>, Error> {
+        let file_name = format!("{} - {}.lrc", artist, title).replace(['/', '\\'], "_");
+        match tokio::fs::read_to_string(self.dir.join(file_name)).await {
+            Ok(lrc) => Ok(Some(Lyrics::parse_lrc(&lrc))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// https://lrclib.net
+pub struct LrclibProvider {
+    pub http_client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+    synced_lyrics: Option<String>,
+    plain_lyrics: Option<String>,
+}
+
+impl LrclibProvider {
+    const URL: &'static str = "https://lrclib.net/api";
+}
+
+#[async_trait]
+impl Provider for LrclibProvider {
+    async fn lookup(&self, artist: &str, title: &str, duration: Option<Duration>) -> Result<Option<Lyrics>, Error> {
+        let mut query = vec![("artist_name", artist.to_string()), ("track_name", title.to_string())];
+        if let Some(duration) = duration {
+            query.push(("duration", duration.as_secs().to_string()));
+        }
+
+        let response = self
+            .http_client
+            .get(format!("{}/get", Self::URL))
+            .query(&query)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let track: LrclibTrack = serde_json::from_str(&response.error_for_status()?.text().await?)?;
+        Ok(track
+            .synced_lyrics
+            .or(track.plain_lyrics)
+            .map(|lrc| Lyrics::parse_lrc(&lrc)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn parse_timestamp_rejects_invalid_numbers() {
+        assert_eq!(parse_timestamp("01:02.50"), Some(secs(62.5)));
+        assert_eq!(parse_timestamp("ar:artist"), None);
+        assert_eq!(parse_timestamp("00:nan"), None);
+        assert_eq!(parse_timestamp("00:inf"), None);
+        assert_eq!(parse_timestamp("00:-1"), None);
+        assert_eq!(parse_timestamp(&format!("{}:00", u64::MAX)), None);
+    }
+
+    #[test]
+    fn parse_lrc_synced() {
+        let lyrics = Lyrics::parse_lrc(
+            "[ar:artist]\n[ti:title]\n[00:12.00]first\n[00:05.50]zero\n[00:20.00][01:00.00]chorus\n\n",
+        );
+        let lines: Vec<_> = lyrics.lines.iter().map(|line| (line.time, line.text.as_str())).collect();
+        assert_eq!(
+            lines,
+            [
+                (Some(secs(5.5)), "zero"),
+                (Some(secs(12.0)), "first"),
+                (Some(secs(20.0)), "chorus"),
+                (Some(secs(60.0)), "chorus"),
+            ]
+        );
+        assert!(lyrics.is_synced());
+    }
+
+    #[test]
+    fn parse_lrc_plain() {
+        let lyrics = Lyrics::parse_lrc("first line\n\nsecond line\n");
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.text(), "first line\nsecond line");
+    }
+
+    #[test]
+    fn parse_lrc_invalid_timestamps_do_not_panic() {
+        let lyrics = Lyrics::parse_lrc("[00:nan]a\n[00:-3]b\n[00:01]c");
+        assert_eq!(lyrics.lines.iter().filter(|line| line.time.is_some()).count(), 1);
+    }
+
+    #[test]
+    fn line_at_position() {
+        let lyrics = Lyrics::parse_lrc("[00:05]a\n[00:10]b\n[00:15]c");
+        assert_eq!(lyrics.line_at(secs(0.0)), None);
+        assert_eq!(lyrics.line_at(secs(5.0)), Some(0));
+        assert_eq!(lyrics.line_at(secs(12.0)), Some(1));
+        assert_eq!(lyrics.line_at(secs(100.0)), Some(2));
+    }
+
+    #[test]
+    fn strip_brackets_removes_suffixes() {
+        assert_eq!(strip_brackets("Song (Official Video)"), "Song");
+        assert_eq!(strip_brackets("[MV] Song [4K] (Live)"), "Song");
+        assert_eq!(strip_brackets("Song (feat. A (B))  Title"), "Song Title");
+        assert_eq!(strip_brackets("Song)"), "Song");
+    }
+
+    fn metadata(title: &str, artist: Option<&str>, channel: Option<&str>) -> songbird::input::AuxMetadata {
+        songbird::input::AuxMetadata {
+            title: Some(title.to_string()),
+            artist: artist.map(str::to_string),
+            channel: channel.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn terms(artist: &str, title: &str) -> Option<(String, String)> {
+        Some((artist.to_string(), title.to_string()))
+    }
+
+    #[test]
+    fn search_terms_from_metadata() {
+        assert_eq!(search_terms(&metadata("Song (Official Video)", Some("Artist"), None)), terms("Artist", "Song"));
+        assert_eq!(search_terms(&metadata("Song", None, Some("Artist - Topic"))), terms("Artist", "Song"));
+    }
+
+    #[test]
+    fn search_terms_guessed_from_title() {
+        assert_eq!(search_terms(&metadata("Artist - Song [MV]", None, Some("Label"))), terms("Artist", "Song"));
+        assert_eq!(search_terms(&metadata("Artist - Song", None, None)), terms("Artist", "Song"));
+        assert_eq!(search_terms(&metadata("Song", None, None)), None);
+        assert_eq!(search_terms(&songbird::input::AuxMetadata::default()), None);
+    }
+
+    #[tokio::test]
+    async fn lookup_through_file_provider() {
+        let dir = std::env::temp_dir().join(format!("ui-chan-lyrics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Artist - Song.lrc"), "[00:01.00]hello\n[00:02.00]world").unwrap();
+        std::fs::write(dir.join("Artist - Empty.lrc"), "[ar:Artist]").unwrap();
+
+        let providers: Vec<Box<dyn Provider>> = vec![Box::new(FileProvider { dir: dir.clone() })];
+        let found = lookup(&providers, "Artist", "Song", None).await;
+        let empty = lookup(&providers, "Artist", "Empty", None).await;
+        let missing = lookup(&providers, "Artist", "Missing", None).await;
+        std::fs::remove_dir_all(&dir).ok();
+
+        let found = found.unwrap();
+        assert!(found.is_synced());
+        assert_eq!(found.text(), "hello\nworld");
+        assert!(empty.is_none());
+        assert!(missing.is_none());
+    }
+}
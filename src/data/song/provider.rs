@@ -0,0 +1,521 @@
+use poise::serenity_prelude::async_trait;
+use rspotify::clients::BaseClient;
+use songbird::input::{AuxMetadata, Compose, Input, YoutubeDl};
+
+use crate::prelude::*;
+
+use super::{report_progress, spotify_request, spotify_search_query, spotify_track_source, stream, Playlist, SPOTIFY_MARKET};
+
+/// Domain of `link`, or `None` if it is a search query rather than a link.
+pub fn domain(link: &str) -> Option<&str> {
+    let link = link.trim();
+    if link.contains(char::is_whitespace) {
+        return None;
+    }
+    let domain = link
+        .trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .split(['/', '?', '#'])
+        .next()?;
+    domain.contains('.').then_some(domain)
+}
+
+/// Path segments of `link`, without the query.
+fn segments(link: &str) -> impl Iterator<Item = &str> {
+    link.trim()
+        .trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .skip(1)
+        .filter(|segment| !segment.is_empty())
+}
+
+/// Resolves the links of a site into songs.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    fn matches(&self, link: &str) -> bool;
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error>;
+
+    /// Input of the link, along with its metadata if they were looked up to find the song.
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error>;
+
+    /// Url or path of the audio which ffmpeg can read.
+    async fn media_location(&self, link: &str) -> Result<String, Error>;
+
+    /// Whether the link is a collection of songs, such as a playlist or an album.
+    fn is_playlist(&self, _link: &str) -> bool {
+        false
+    }
+
+    async fn playlist(
+        &self,
+        _ctx: &serenity::Context,
+        _link: &str,
+        _limit: usize,
+        _progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        Ok(None)
+    }
+
+    /// Whether the link may be a radio stream, which announces the current title.
+    /// Such links are played by `stream::open`, which finds out if they do.
+    fn is_live(&self, _link: &str) -> bool {
+        false
+    }
+}
+
+/// The first provider which handles `link`.
+pub fn find<'a>(providers: &'a [Box<dyn Provider>], link: &str) -> Option<&'a dyn Provider> {
+    domain(link)?;
+    providers
+        .iter()
+        .find(|provider| provider.matches(link))
+        .map(|provider| provider.as_ref())
+}
+
+async fn ytdlp_metadata(http_client: &reqwest::Client, url: &str) -> Result<AuxMetadata, Error> {
+    Ok(YoutubeDl::new(http_client.clone(), url.to_string()).aux_metadata().await?)
+}
+
+/// Direct url of the audio, for sites supported by yt-dlp.
+pub async fn ytdlp_media_location(url: &str) -> Result<String, Error> {
+    let output = tokio::process::Command::new("yt-dlp")
+        .args(["-f", "bestaudio/best", "-g", "--no-playlist"])
+        .arg(url)
+        .output()
+        .await?;
+    String::from_utf8(output.stdout)?
+        .lines()
+        .next()
+        .map(str::to_string)
+        .ok_or(Error::from("Cannot find the audio stream."))
+}
+
+pub struct YoutubeProvider {
+    pub http_client: reqwest::Client,
+}
+
+#[async_trait]
+impl Provider for YoutubeProvider {
+    fn matches(&self, link: &str) -> bool {
+        domain(link).is_some_and(|domain| domain.contains("youtube") || domain.contains("youtu.be"))
+    }
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error> {
+        ytdlp_metadata(&self.http_client, link).await
+    }
+
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error> {
+        Ok((YoutubeDl::new(self.http_client.clone(), link.to_string()).into(), None))
+    }
+
+    async fn media_location(&self, link: &str) -> Result<String, Error> {
+        ytdlp_media_location(link).await
+    }
+
+    fn is_playlist(&self, link: &str) -> bool {
+        link.contains("list=")
+    }
+
+    async fn playlist(
+        &self,
+        ctx: &serenity::Context,
+        link: &str,
+        limit: usize,
+        progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        Ok(Some(super::youtube_playlist(ctx, link, limit, progress_message).await?))
+    }
+}
+
+/// Spotify has no audio to stream, so its songs are searched on YouTube.
+pub struct SpotifyProvider {
+    pub http_client: reqwest::Client,
+    pub spotify: rspotify::ClientCredsSpotify,
+}
+
+impl SpotifyProvider {
+    /// Kind and id of the link, such as `("track", id)`.
+    fn parse(link: &str) -> Option<(&str, &str)> {
+        let mut segments = segments(link);
+        let mut kind = segments.next()?;
+        if kind.starts_with("intl-") {
+            kind = segments.next()?;
+        }
+        Some((kind, segments.next()?))
+    }
+
+    /// The track of the link, and its search on YouTube.
+    async fn search(&self, link: &str) -> Result<(YoutubeDl, rspotify::model::FullTrack), Error> {
+        let track_id = match Self::parse(link) {
+            Some(("track", id)) => rspotify::model::TrackId::from_id(id)?,
+            _ => return Err(Error::from("Spotify collection links cannot be played as a single song.")),
+        };
+        let track = spotify_request(&self.spotify, || self.spotify.track(track_id.clone(), None)).await?;
+        let search_str = spotify_search_query(&track.artists, &track.name);
+        Ok((YoutubeDl::new_search(self.http_client.clone(), search_str), track))
+    }
+}
+
+#[async_trait]
+impl Provider for SpotifyProvider {
+    fn matches(&self, link: &str) -> bool {
+        domain(link).is_some_and(|domain| domain.contains("spotify"))
+    }
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error> {
+        Ok(self.search(link).await?.0.aux_metadata().await?)
+    }
+
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error> {
+        let (source, track) = self.search(link).await?;
+        let metadata = AuxMetadata {
+            title: Some(track.name),
+            artist: Some(track.artists.iter().map(|artist| artist.name.as_str()).collect::<Vec<_>>().join(", ")),
+            duration: track.duration.to_std().ok(),
+            ..Default::default()
+        };
+        Ok((source.into(), Some(metadata)))
+    }
+
+    async fn media_location(&self, link: &str) -> Result<String, Error> {
+        let url = self
+            .aux_metadata(link)
+            .await?
+            .source_url
+            .ok_or(Error::from("Cannot find the song url."))?;
+        ytdlp_media_location(&url).await
+    }
+
+    fn is_playlist(&self, link: &str) -> bool {
+        matches!(Self::parse(link), Some(("playlist" | "album" | "artist", _)))
+    }
+
+    async fn playlist(
+        &self,
+        ctx: &serenity::Context,
+        link: &str,
+        limit: usize,
+        mut progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        let spotify = &self.spotify;
+
+        match Self::parse(link) {
+            Some(("playlist", playlist_id)) => {
+                let playlist_id = rspotify::model::PlaylistId::from_id(playlist_id)?;
+                let playlist = spotify_request(spotify, || spotify.playlist(playlist_id.clone(), None, Some(SPOTIFY_MARKET))).await?;
+
+                let mut sources = Vec::new();
+                let mut total = 0;
+                let mut offset = 0;
+                while sources.len() < limit {
+                    let page = spotify_request(spotify, || {
+                        spotify.playlist_items_manual(playlist_id.clone(), None, Some(SPOTIFY_MARKET), Some(50), Some(offset))
+                    }).await?;
+                    total = page.total as usize;
+
+                    for item in &page.items {
+                        if let Some(rspotify::model::PlayableItem::Track(track)) = item.track.as_ref() {
+                            sources.push(spotify_track_source(track.id.as_ref(), &track.artists, &track.name));
+                        }
+                    }
+
+                    if page.next.is_none() {
+                        break;
+                    }
+                    report_progress(ctx, &mut progress_message, &playlist.name, sources.len()).await;
+                    offset += page.items.len() as u32;
+                }
+                sources.truncate(limit);
+
+                Ok(Some(Playlist { title: playlist.name, sources, total }))
+            },
+            Some(("album", album_id)) => {
+                let album_id = rspotify::model::AlbumId::from_id(album_id)?;
+                let album = spotify_request(spotify, || spotify.album(album_id.clone(), Some(SPOTIFY_MARKET))).await?;
+
+                let mut tracks = album.tracks.items;
+                while tracks.len() < limit && tracks.len() < album.tracks.total as usize {
+                    let offset = tracks.len() as u32;
+                    let page = spotify_request(spotify, || {
+                        spotify.album_track_manual(album_id.clone(), Some(SPOTIFY_MARKET), Some(50), Some(offset))
+                    }).await?;
+                    if page.items.is_empty() {
+                        break;
+                    }
+                    tracks.extend(page.items);
+                }
+
+                let sources = tracks
+                    .iter()
+                    .take(limit)
+                    .map(|track| spotify_track_source(track.id.as_ref(), &track.artists, &track.name))
+                    .collect();
+
+                Ok(Some(Playlist { title: album.name, sources, total: album.tracks.total as usize }))
+            },
+            Some(("artist", artist_id)) => {
+                let artist_id = rspotify::model::ArtistId::from_id(artist_id)?;
+                let artist = spotify_request(spotify, || spotify.artist(artist_id.clone())).await?;
+                let tracks = spotify_request(spotify, || spotify.artist_top_tracks(artist_id.clone(), Some(SPOTIFY_MARKET))).await?;
+
+                let total = tracks.len();
+                let sources = tracks
+                    .iter()
+                    .take(limit)
+                    .map(|track| spotify_track_source(track.id.as_ref(), &track.artists, &track.name))
+                    .collect();
+
+                Ok(Some(Playlist { title: artist.name, sources, total }))
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+pub struct SoundCloudProvider {
+    pub http_client: reqwest::Client,
+}
+
+#[async_trait]
+impl Provider for SoundCloudProvider {
+    fn matches(&self, link: &str) -> bool {
+        domain(link).is_some_and(|domain| domain.ends_with("soundcloud.com"))
+    }
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error> {
+        ytdlp_metadata(&self.http_client, link).await
+    }
+
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error> {
+        Ok((YoutubeDl::new(self.http_client.clone(), link.to_string()).into(), None))
+    }
+
+    async fn media_location(&self, link: &str) -> Result<String, Error> {
+        ytdlp_media_location(link).await
+    }
+
+    /// `soundcloud.com/{user}/sets/{playlist}`
+    fn is_playlist(&self, link: &str) -> bool {
+        segments(link).nth(1) == Some("sets")
+    }
+
+    async fn playlist(
+        &self,
+        ctx: &serenity::Context,
+        link: &str,
+        limit: usize,
+        progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        Ok(Some(super::youtube_playlist(ctx, link, limit, progress_message).await?))
+    }
+}
+
+pub struct BandcampProvider {
+    pub http_client: reqwest::Client,
+}
+
+#[async_trait]
+impl Provider for BandcampProvider {
+    fn matches(&self, link: &str) -> bool {
+        domain(link).is_some_and(|domain| domain.ends_with("bandcamp.com"))
+    }
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error> {
+        ytdlp_metadata(&self.http_client, link).await
+    }
+
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error> {
+        Ok((YoutubeDl::new(self.http_client.clone(), link.to_string()).into(), None))
+    }
+
+    async fn media_location(&self, link: &str) -> Result<String, Error> {
+        ytdlp_media_location(link).await
+    }
+
+    /// `{artist}.bandcamp.com/album/{album}`
+    fn is_playlist(&self, link: &str) -> bool {
+        segments(link).next() == Some("album")
+    }
+
+    async fn playlist(
+        &self,
+        ctx: &serenity::Context,
+        link: &str,
+        limit: usize,
+        progress_message: Option<&mut serenity::Message>,
+    ) -> Result<Option<Playlist>, Error> {
+        Ok(Some(super::youtube_playlist(ctx, link, limit, progress_message).await?))
+    }
+}
+
+/// Direct media files and radio streams. It matches any http link, so it goes last,
+/// and leaves the pages of other sites to yt-dlp.
+pub struct HttpProvider {
+    pub http_client: reqwest::Client,
+}
+
+#[async_trait]
+impl Provider for HttpProvider {
+    fn matches(&self, link: &str) -> bool {
+        let link = link.trim();
+        link.starts_with("http://") || link.starts_with("https://")
+    }
+
+    async fn aux_metadata(&self, link: &str) -> Result<AuxMetadata, Error> {
+        if stream::is_media(&self.http_client, link).await {
+            stream::aux_metadata(&self.http_client, link).await
+        } else {
+            ytdlp_metadata(&self.http_client, link).await
+        }
+    }
+
+    async fn input(&self, link: &str) -> Result<(Input, Option<AuxMetadata>), Error> {
+        if stream::is_media(&self.http_client, link).await {
+            Ok((stream::input(&self.http_client, link)?, None))
+        } else {
+            Ok((YoutubeDl::new(self.http_client.clone(), link.to_string()).into(), None))
+        }
+    }
+
+    async fn media_location(&self, link: &str) -> Result<String, Error> {
+        if stream::is_media(&self.http_client, link).await {
+            Ok(link.to_string())
+        } else {
+            ytdlp_media_location(link).await
+        }
+    }
+
+    fn is_live(&self, link: &str) -> bool {
+        !stream::is_hls(link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn providers() -> Vec<Box<dyn Provider>> {
+        let http_client = reqwest::Client::new();
+        vec![
+            Box::new(YoutubeProvider { http_client: http_client.clone() }),
+            Box::new(SpotifyProvider {
+                http_client: http_client.clone(),
+                spotify: rspotify::ClientCredsSpotify::default(),
+            }),
+            Box::new(SoundCloudProvider { http_client: http_client.clone() }),
+            Box::new(BandcampProvider { http_client: http_client.clone() }),
+            Box::new(HttpProvider { http_client }),
+        ]
+    }
+
+    /// Index of the provider of `link`, in the order of `providers`.
+    fn provider_index(link: &str) -> Option<usize> {
+        let providers = providers();
+        let provider = find(&providers, link)?;
+        providers.iter().position(|candidate| std::ptr::addr_eq(candidate.as_ref(), provider))
+    }
+
+    #[test]
+    fn domain_of_links() {
+        assert_eq!(domain("https://www.youtube.com/watch?v=id"), Some("www.youtube.com"));
+        assert_eq!(domain(" youtu.be/id "), Some("youtu.be"));
+        assert_eq!(domain("http://example.com?query"), Some("example.com"));
+        assert_eq!(domain("artist - song"), None);
+        assert_eq!(domain("song"), None);
+        assert_eq!(domain("https://localhost/song.mp3"), None);
+    }
+
+    #[test]
+    fn segments_of_links() {
+        let segments = |link| segments(link).collect::<Vec<_>>();
+        assert_eq!(segments("https://open.spotify.com/track/id?si=x"), ["track", "id"]);
+        assert_eq!(segments("soundcloud.com/user//sets/name/#top"), ["user", "sets", "name"]);
+        assert!(segments("https://example.com").is_empty());
+    }
+
+    #[test]
+    fn spotify_parse() {
+        assert_eq!(SpotifyProvider::parse("https://open.spotify.com/track/id?si=x"), Some(("track", "id")));
+        assert_eq!(SpotifyProvider::parse("https://open.spotify.com/intl-ko/album/id"), Some(("album", "id")));
+        assert_eq!(SpotifyProvider::parse("https://open.spotify.com/intl-ko"), None);
+        assert_eq!(SpotifyProvider::parse("https://open.spotify.com/track"), None);
+    }
+
+    #[test]
+    fn find_provider_of_links() {
+        assert_eq!(provider_index("https://www.youtube.com/watch?v=id"), Some(0));
+        assert_eq!(provider_index("https://youtu.be/id"), Some(0));
+        assert_eq!(provider_index("https://open.spotify.com/track/id"), Some(1));
+        assert_eq!(provider_index("https://soundcloud.com/user/song"), Some(2));
+        assert_eq!(provider_index("https://artist.bandcamp.com/track/song"), Some(3));
+        assert_eq!(provider_index("https://example.com/radio.mp3"), Some(4));
+        assert_eq!(provider_index("example.com/song.mp3"), None);
+        assert_eq!(provider_index("artist - song"), None);
+    }
+
+    #[test]
+    fn playlist_links() {
+        let is_playlist = |link| find(&providers(), link).is_some_and(|provider| provider.is_playlist(link));
+        assert!(is_playlist("https://www.youtube.com/playlist?list=id"));
+        assert!(is_playlist("https://www.youtube.com/watch?v=id&list=id"));
+        assert!(!is_playlist("https://www.youtube.com/watch?v=id"));
+        assert!(is_playlist("https://open.spotify.com/playlist/id"));
+        assert!(is_playlist("https://open.spotify.com/intl-ko/album/id"));
+        assert!(is_playlist("https://open.spotify.com/artist/id"));
+        assert!(!is_playlist("https://open.spotify.com/track/id"));
+        assert!(is_playlist("https://soundcloud.com/user/sets/name"));
+        assert!(!is_playlist("https://soundcloud.com/user/song"));
+        assert!(is_playlist("https://artist.bandcamp.com/album/name"));
+        assert!(!is_playlist("https://artist.bandcamp.com/track/name"));
+        assert!(!is_playlist("https://example.com/list=song.mp3"));
+    }
+
+    #[test]
+    fn live_links() {
+        let is_live = |link| find(&providers(), link).is_some_and(|provider| provider.is_live(link));
+        assert!(is_live("https://example.com/radio"));
+        assert!(!is_live("https://example.com/live.m3u8?token=x"));
+        assert!(!is_live("https://www.youtube.com/watch?v=id"));
+    }
+
+    /// Url of a local server answering every request with `content_type`.
+    async fn serve(content_type: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/page", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buffer = [0; 1024];
+                if socket.read(&mut buffer).await.is_err() {
+                    continue;
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    content_type
+                );
+                socket.write_all(response.as_bytes()).await.ok();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn http_pages_are_left_to_ytdlp() {
+        let http_client = reqwest::Client::new();
+        let page = serve("text/html; charset=utf-8").await;
+        let audio = serve("audio/mpeg").await;
+
+        assert!(!stream::is_media(&http_client, &page).await);
+        assert!(stream::is_media(&http_client, &audio).await);
+        assert!(stream::is_media(&http_client, "https://example.invalid/live.m3u8").await);
+
+        let provider = HttpProvider { http_client };
+        assert_eq!(provider.media_location(&audio).await.unwrap(), audio);
+    }
+}
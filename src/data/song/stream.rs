@@ -0,0 +1,347 @@
+use poise::serenity_prelude::async_trait;
+use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWriteExt};
+
+use crate::prelude::*;
+
+use super::Filter;
+
+/// Bytes of audio buffered between the radio stream and the player.
+const STREAM_BUFFER: usize = 1 << 16;
+
+fn path(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+/// HLS playlists are not supported by songbird, so they are streamed through ffmpeg.
+pub fn is_hls(url: &str) -> bool {
+    path(url).to_lowercase().ends_with(".m3u8")
+}
+
+pub fn input(http_client: &reqwest::Client, url: &str) -> Result<songbird::input::Input, Error> {
+    if is_hls(url) {
+        super::ffmpeg_input(url, &Filter::default(), std::time::Duration::ZERO)
+    } else {
+        Ok(songbird::input::HttpRequest::new(http_client.clone(), url.to_string()).into())
+    }
+}
+
+async fn request(http_client: &reqwest::Client, url: &str) -> Result<reqwest::Response, Error> {
+    Ok(http_client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await?
+        .error_for_status()?)
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Whether the content type is audio or video, rather than a web page.
+fn is_media_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    mime.starts_with("audio/")
+        || mime.starts_with("video/")
+        || matches!(mime.as_str(), "application/ogg" | "application/vnd.apple.mpegurl" | "application/x-mpegurl")
+}
+
+fn is_media_response(response: &reqwest::Response) -> bool {
+    header(response, "icy-metaint").is_some() || header(response, "content-type").is_some_and(|content_type| is_media_type(&content_type))
+}
+
+/// Whether `url` is a media file or stream, rather than a page of a site such as Twitch or Vimeo.
+pub async fn is_media(http_client: &reqwest::Client, url: &str) -> bool {
+    if is_hls(url) {
+        return true;
+    }
+    // some servers, such as radio streams, do not answer HEAD
+    match http_client.head(url).send().await.and_then(|response| response.error_for_status()) {
+        Ok(response) if is_media_response(&response) => true,
+        _ => request(http_client, url).await.is_ok_and(|response| is_media_response(&response)),
+    }
+}
+
+/// Metadata from the response headers. Radio streams are titled by their station name.
+fn metadata(response: &reqwest::Response, url: &str) -> songbird::input::AuxMetadata {
+    let file_name = path(url)
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty() && !name.contains(':'))
+        .map(str::to_string);
+
+    songbird::input::AuxMetadata {
+        title: header(response, "icy-name").or(file_name).or_else(|| Some(url.to_string())),
+        source_url: Some(url.to_string()),
+        ..Default::default()
+    }
+}
+
+pub async fn aux_metadata(http_client: &reqwest::Client, url: &str) -> Result<songbird::input::AuxMetadata, Error> {
+    Ok(metadata(&request(http_client, url).await?, url))
+}
+
+/// Title announced by a radio stream, read from the connection it is played from.
+#[derive(Default)]
+pub struct LiveTitle {
+    is_live: AtomicBool,
+    title: std::sync::Mutex<Option<String>>,
+}
+
+impl LiveTitle {
+    /// Whether the stream being played announces its titles.
+    pub fn is_live(&self) -> bool {
+        self.is_live.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self) -> Option<String> {
+        self.title.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.is_live.store(false, Ordering::Relaxed);
+        *self.title.lock().unwrap() = None;
+    }
+}
+
+/// Starts streaming `url` along with its metadata, which come from the same response,
+/// or `None` if it is a web page rather than media.
+/// If it is an Icecast/SHOUTcast stream, the titles it announces are kept in `live_title` while it plays.
+pub async fn open(
+    http_client: &reqwest::Client,
+    url: &str,
+    live_title: &Arc<LiveTitle>,
+) -> Result<Option<(songbird::input::Input, songbird::input::AuxMetadata)>, Error> {
+    live_title.reset();
+
+    let response = request(http_client, url).await?;
+    if !is_media_response(&response) {
+        return Ok(None);
+    }
+    let metadata = metadata(&response, url);
+    let metaint = match header(&response, "icy-metaint").and_then(|value| value.parse().ok()) {
+        Some(metaint) if metaint > 0 => metaint,
+        // not a radio stream, so it is requested again by songbird, which can seek in it
+        _ => return Ok(Some((input(http_client, url)?, metadata))),
+    };
+
+    let mut hint = songbird::input::core::probe::Hint::new();
+    if let Some(content_type) = header(&response, "content-type") {
+        hint.mime_type(&content_type);
+    }
+
+    live_title.is_live.store(true, Ordering::Relaxed);
+    let (reader, writer) = tokio::io::duplex(STREAM_BUFFER);
+    tokio::spawn(demux(response, metaint, writer, live_title.clone()));
+
+    let stream = songbird::input::AsyncAdapterStream::new(Box::new(IcyStream(reader)), STREAM_BUFFER);
+    let input = songbird::input::Input::Live(
+        songbird::input::LiveInput::Raw(songbird::input::AudioStream {
+            input: Box::new(stream),
+            hint: Some(hint),
+        }),
+        None,
+    );
+    Ok(Some((input, metadata)))
+}
+
+/// Passes the audio of the response to `writer`, until the track reading it has ended.
+async fn demux(
+    mut response: reqwest::Response,
+    metaint: usize,
+    mut writer: tokio::io::DuplexStream,
+    live_title: Arc<LiveTitle>,
+) {
+    let mut demuxer = IcyDemuxer::new(metaint);
+    let mut audio = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        audio.clear();
+        if let Some(title) = demuxer.push(&chunk, &mut audio) {
+            *live_title.title.lock().unwrap() = Some(title);
+        }
+        if writer.write_all(&audio).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Splits an ICY stream into its audio and its metadata blocks.
+/// A block follows every `metaint` bytes of audio, prefixed by its length in 16 bytes.
+struct IcyDemuxer {
+    metaint: usize,
+    /// Bytes of audio left before the next block.
+    audio_left: usize,
+    block: Vec<u8>,
+    /// Length of the block being read, once its prefix is read.
+    block_len: Option<usize>,
+}
+
+impl IcyDemuxer {
+    fn new(metaint: usize) -> Self {
+        Self {
+            metaint,
+            audio_left: metaint,
+            block: Vec::new(),
+            block_len: None,
+        }
+    }
+
+    /// Appends the audio in `chunk` to `audio`, and returns the last title announced in it.
+    fn push(&mut self, mut chunk: &[u8], audio: &mut Vec<u8>) -> Option<String> {
+        let mut title = None;
+        while !chunk.is_empty() {
+            if self.audio_left > 0 {
+                let n = self.audio_left.min(chunk.len());
+                audio.extend_from_slice(&chunk[..n]);
+                chunk = &chunk[n..];
+                self.audio_left -= n;
+                continue;
+            }
+
+            let block_len = match self.block_len {
+                Some(block_len) => {
+                    let n = (block_len - self.block.len()).min(chunk.len());
+                    self.block.extend_from_slice(&chunk[..n]);
+                    chunk = &chunk[n..];
+                    block_len
+                }
+                None => {
+                    let block_len = chunk[0] as usize * 16;
+                    self.block_len = Some(block_len);
+                    chunk = &chunk[1..];
+                    block_len
+                }
+            };
+
+            if self.block.len() == block_len {
+                if let Some(block_title) = parse_stream_title(&String::from_utf8_lossy(&self.block)) {
+                    title = Some(block_title);
+                }
+                self.block.clear();
+                self.block_len = None;
+                self.audio_left = self.metaint;
+            }
+        }
+        title
+    }
+}
+
+/// `StreamTitle='artist - title';StreamUrl='...';`
+fn parse_stream_title(block: &str) -> Option<String> {
+    let start = block.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &block[start..];
+    let end = rest.find("';").unwrap_or(rest.trim_end_matches('\0').len());
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Audio of a radio stream, which cannot be seeked.
+struct IcyStream(tokio::io::DuplexStream);
+
+impl AsyncRead for IcyStream {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for IcyStream {
+    fn start_seek(self: std::pin::Pin<&mut Self>, _position: std::io::SeekFrom) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_complete(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<u64>> {
+        std::task::Poll::Ready(Err(std::io::ErrorKind::Unsupported.into()))
+    }
+}
+
+#[async_trait]
+impl songbird::input::AsyncMediaSource for IcyStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    async fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_content_types() {
+        assert!(is_media_type("audio/mpeg"));
+        assert!(is_media_type("Video/MP4; codecs=avc1"));
+        assert!(is_media_type("application/vnd.apple.mpegurl"));
+        assert!(!is_media_type("text/html; charset=utf-8"));
+        assert!(!is_media_type("application/json"));
+    }
+
+    #[test]
+    fn parse_stream_title_from_block() {
+        assert_eq!(
+            parse_stream_title("StreamTitle='Artist - Song';StreamUrl='http://example.com';\0\0"),
+            Some("Artist - Song".to_string())
+        );
+        assert_eq!(parse_stream_title("StreamTitle='It's';\0"), Some("It's".to_string()));
+        assert_eq!(parse_stream_title("StreamTitle='Unterminated\0\0\0"), Some("Unterminated".to_string()));
+        assert_eq!(parse_stream_title("StreamTitle='';\0"), None);
+        assert_eq!(parse_stream_title("StreamUrl='http://example.com';"), None);
+    }
+
+    /// `metaint` bytes of audio, then the block padded to 16 bytes.
+    fn icy_stream(audio: &[&[u8]], blocks: &[&str]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for (audio, block) in audio.iter().zip(blocks) {
+            stream.extend_from_slice(audio);
+            let mut block = block.as_bytes().to_vec();
+            block.resize(block.len().div_ceil(16) * 16, 0);
+            stream.push((block.len() / 16) as u8);
+            stream.extend_from_slice(&block);
+        }
+        stream
+    }
+
+    #[test]
+    fn demuxer_separates_audio_and_titles() {
+        let stream = icy_stream(&[b"abcd", b"efgh", b"ijkl"], &["StreamTitle='First';", "", "StreamTitle='Second';"]);
+
+        let mut demuxer = IcyDemuxer::new(4);
+        let mut audio = Vec::new();
+        assert_eq!(demuxer.push(&stream, &mut audio), Some("Second".to_string()));
+        assert_eq!(audio, b"abcdefghijkl");
+    }
+
+    #[test]
+    fn demuxer_handles_split_chunks() {
+        let mut stream = icy_stream(&[b"abcd", b"efgh"], &["StreamTitle='First';", "StreamTitle='Second';"]);
+        stream.extend_from_slice(b"ij");
+
+        for chunk_len in 1..stream.len() {
+            let mut demuxer = IcyDemuxer::new(4);
+            let mut audio = Vec::new();
+            let titles: Vec<_> = stream
+                .chunks(chunk_len)
+                .filter_map(|chunk| demuxer.push(chunk, &mut audio))
+                .collect();
+            assert_eq!(audio, b"abcdefghij", "chunk_len = {}", chunk_len);
+            assert_eq!(titles.last().map(String::as_str), Some("Second"), "chunk_len = {}", chunk_len);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, StandardTagKey, Tag},
+    probe::Hint,
+};
+
+use crate::prelude::*;
+
+/// Extensions of the audio files that can be played from disk.
+const EXTENSIONS: [&str; 6] = ["mp3", "flac", "ogg", "oga", "opus", "wav"];
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+#[derive(Clone, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub track_number: Option<u32>,
+    pub duration: Option<std::time::Duration>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+}
+
+impl Tags {
+    /// Reads the tags of an audio file. This blocks, so call it in `spawn_blocking`.
+    pub fn read(file: &Path) -> Result<Self, Error> {
+        let source = MediaSourceStream::new(Box::new(std::fs::File::open(file)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = file.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let mut probed = symphonia::default::get_probe().format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+
+        // id3 tags come before the container, vorbis comments are inside it
+        let mut tags: Vec<Tag> = Vec::new();
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|metadata| metadata.current()) {
+            tags.extend(revision.tags().iter().cloned());
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            tags.extend(revision.tags().iter().cloned());
+        }
+
+        let mut result = Self::default();
+        for tag in tags {
+            let value = Some(tag.value.to_string());
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => result.title = value,
+                Some(StandardTagKey::Artist) => result.artist = value,
+                Some(StandardTagKey::Album) => result.album = value,
+                Some(StandardTagKey::Date) => result.date = value,
+                // "3" or "3/12"
+                Some(StandardTagKey::TrackNumber) => {
+                    result.track_number = tag.value.to_string().split('/').next().and_then(|number| number.trim().parse().ok());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(track) = probed.format.default_track() {
+            let params = &track.codec_params;
+            result.sample_rate = params.sample_rate;
+            result.channels = params.channels.map(|channels| channels.count() as u8);
+            if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+                let time = time_base.calc_time(frames);
+                result.duration = Some(std::time::Duration::from_secs(time.seconds) + std::time::Duration::from_secs_f64(time.frac));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// "artist - title", as the title of YouTube songs.
+    pub fn display_title(&self) -> Option<String> {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (_, title) => title.clone(),
+        }
+    }
+
+    pub fn aux_metadata(&self) -> songbird::input::AuxMetadata {
+        songbird::input::AuxMetadata {
+            title: self.title.clone(),
+            track: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            date: self.date.clone(),
+            duration: self.duration,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            ..Default::default()
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{command, data, prelude::*};
+use crate::{command, data, data::song, prelude::*};
 
 pub async fn event_handler(
     ctx: &serenity::Context,
@@ -12,6 +12,9 @@ pub async fn event_handler(
         }
         serenity::FullEvent::CacheReady { guilds } => {
             println!("Cache ready! {:?}", guilds);
+            for guild_id in guilds {
+                command::song::restore::restore(ctx, *guild_id).await.ok();
+            }
         }
         serenity::FullEvent::Ratelimit { data } => {
             println!("ratelilmit! {:?}", data);
@@ -26,6 +29,34 @@ pub async fn event_handler(
         } => {
             handle_queue_delete(ctx, deleted_message_id, guild_id).await;
         }
+        serenity::FullEvent::ReactionAdd { add_reaction } => {
+            handle_queue_vote(ctx, add_reaction, true).await;
+            handle_skip_vote(ctx, add_reaction, true).await;
+        }
+        serenity::FullEvent::ReactionRemove { removed_reaction } => {
+            handle_queue_vote(ctx, removed_reaction, false).await;
+            handle_skip_vote(ctx, removed_reaction, false).await;
+        }
+        serenity::FullEvent::ReactionRemoveAll {
+            channel_id,
+            removed_from_message_id,
+        } => {
+            let guild_id = ctx.cache.guilds().into_iter().find(|guild_id| {
+                ctx.cache
+                    .guild(*guild_id)
+                    .is_some_and(|guild| guild.channels.contains_key(channel_id))
+            });
+            handle_queue_vote_clear(ctx, guild_id, *removed_from_message_id).await;
+        }
+        serenity::FullEvent::ReactionRemoveEmoji { removed_reactions } if is_queue_vote(removed_reactions) => {
+            handle_queue_vote_clear(ctx, removed_reactions.guild_id, removed_reactions.message_id).await;
+        }
+        serenity::FullEvent::InteractionCreate { interaction } => {
+            if let Some(component) = interaction.as_message_component() {
+                command::song::player::handle_button(ctx, component).await?;
+                command::song::restore::handle_button(ctx, component).await?;
+            }
+        }
         serenity::FullEvent::VoiceStateUpdate {
             old: Some(old),
             new: _,
@@ -51,7 +82,7 @@ async fn handle_queue_new_song(ctx: &serenity::Context, message: &serenity::Mess
     };
 
     if is_song_channel {
-        if let Err(err) = command::song::queue_internal(ctx, std::sync::Arc::new(data::song::Request::from(message))).await {
+        if let Err(err) = command::song::request_internal(ctx, data::song::Request::from(message)).await {
             message.reply(ctx, &format!("error: {}", err)).await.ok()?;
         }
     }
@@ -67,20 +98,113 @@ async fn handle_queue_delete(
     let guild_data = data::Storage::guild(ctx, *guild_id).await;
     let mut guild_data = guild_data.lock().await;
 
-    if guild_data
-        .song_now
-        .as_ref()
-        .map_or(false, |now| now.request().message_id == *deleted_message_id)
-    {
+    if guild_data.song_player == Some(*deleted_message_id) {
+        guild_data.song_player = None;
+        drop(guild_data);
+        command::song::player::update_nowait(ctx, *guild_id);
+        return Some(());
+    }
+
+    // the deleter is unknown, but the request cannot be followed without its message either
+    let now = guild_data.song_now.as_ref().map(|now| now.request());
+    if now.is_some_and(|now| now.message_id == *deleted_message_id) {
         drop(guild_data);
         command::song::next_internal(ctx, *guild_id).await.ok()?;
-    } else if let Some(index) = {
+    } else {
+        let num_queue = guild_data.song_queue.len();
         guild_data
             .song_queue
-            .iter()
-            .position(|queue| queue.message_id == *deleted_message_id)
-    } {
-        guild_data.song_queue.remove(index);
+            .retain(|queue| queue.message_id != *deleted_message_id);
+        if guild_data.song_queue.len() == num_queue {
+            return None;
+        }
+        drop(guild_data);
+        command::song::player::update_nowait(ctx, *guild_id);
+        command::song::restore::save_nowait(ctx, *guild_id);
+    }
+
+    Some(())
+}
+
+fn is_queue_vote(reaction: &serenity::Reaction) -> bool {
+    let emoji_queue = song::RequestState::Queue.emoji().unwrap();
+    reaction.emoji.unicode_eq(&emoji_queue.to_string())
+}
+
+async fn handle_queue_vote(
+    ctx: &serenity::Context,
+    reaction: &serenity::Reaction,
+    added: bool,
+) -> Option<()> {
+    if !is_queue_vote(reaction) {
+        return None;
+    }
+    let guild_id = reaction.guild_id?;
+    let user_id = reaction.user_id?;
+    // the bot's own reactions mark the state of the request, they are not votes
+    let is_bot = user_id == ctx.cache.current_user().id
+        || reaction.member.as_ref().is_some_and(|member| member.user.bot)
+        || ctx.cache.user(user_id).is_some_and(|user| user.bot);
+    if is_bot {
+        return None;
+    }
+
+    let guild_data = data::Storage::guild(ctx, guild_id).await;
+    for request in guild_data.lock().await.song_requests_of(reaction.message_id) {
+        request.vote(user_id, added);
+    }
+
+    Some(())
+}
+
+/// Someone cleared the reactions of a request message.
+/// The bot only ever removes its own reactions, which come as `ReactionRemove` and are ignored as a bot's.
+async fn handle_queue_vote_clear(
+    ctx: &serenity::Context,
+    guild_id: Option<serenity::GuildId>,
+    message_id: serenity::MessageId,
+) -> Option<()> {
+    let guild_data = data::Storage::guild(ctx, guild_id?).await;
+    for request in guild_data.lock().await.song_requests_of(message_id) {
+        request.voters.lock().unwrap().clear();
+    }
+
+    Some(())
+}
+
+const SKIP_VOTE_EMOJI: char = '⏭';
+
+
+async fn handle_skip_vote(
+    ctx: &serenity::Context,
+    reaction: &serenity::Reaction,
+    added: bool,
+) -> Option<()> {
+    if !reaction.emoji.unicode_eq(&SKIP_VOTE_EMOJI.to_string()) {
+        return None;
+    }
+    let guild_id = reaction.guild_id?;
+    let user_id = reaction.user_id?;
+    if user_id == ctx.cache.current_user().id {
+        return None;
+    }
+
+    let is_now = {
+        let guild_data = data::Storage::guild(ctx, guild_id).await;
+        let guild_data = guild_data.lock().await;
+        guild_data
+            .song_now
+            .as_ref()
+            .is_some_and(|now| now.request().message_id == reaction.message_id)
+    };
+    if !is_now {
+        return None;
+    }
+
+    if added {
+        command::song::skip_vote_internal(ctx, guild_id, user_id).await.ok()?;
+    } else {
+        command::song::unvote_skip_internal(ctx, guild_id, user_id).await;
     }
 
     Some(())
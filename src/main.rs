@@ -68,12 +68,53 @@ async fn main() {
     {
         let mut data = client.data.write().await;
         
+        let http_client = reqwest::Client::new();
+
+        let mut lyrics: Vec<Box<dyn data::song::lyrics::Provider>> = Vec::new();
+        if let Ok(dir) = std::env::var("LYRICS_DIR") {
+            lyrics.push(Box::new(data::song::lyrics::FileProvider { dir: dir.into() }));
+        }
+        lyrics.push(Box::new(data::song::lyrics::LrclibProvider {
+            http_client: http_client.clone(),
+        }));
+
+        let library = std::env::var("LIBRARY_DIR")
+            .ok()
+            .map(|dir| Arc::new(data::song::library::Library::new(dir.into())));
+        if let Some(library) = library.as_ref() {
+            library.clone().watch();
+        }
+
+        let spotify = {
+            let creds = rspotify::Credentials::from_env().unwrap();
+            rspotify::ClientCredsSpotify::new(creds)
+        };
+
+        let providers: Vec<Box<dyn data::song::provider::Provider>> = vec![
+            Box::new(data::song::provider::YoutubeProvider {
+                http_client: http_client.clone(),
+            }),
+            Box::new(data::song::provider::SpotifyProvider {
+                http_client: http_client.clone(),
+                spotify: spotify.clone(),
+            }),
+            Box::new(data::song::provider::SoundCloudProvider {
+                http_client: http_client.clone(),
+            }),
+            Box::new(data::song::provider::BandcampProvider {
+                http_client: http_client.clone(),
+            }),
+            Box::new(data::song::provider::HttpProvider {
+                http_client: http_client.clone(),
+            }),
+        ];
+
         data.insert::<data::SharedKey>(Arc::new(data::Shared {
-            http_client: reqwest::Client::new(),
-            spotify: {
-                let creds = rspotify::Credentials::from_env().unwrap();
-                rspotify::ClientCredsSpotify::new(creds)
-            }
+            http_client,
+            spotify,
+            lyrics,
+            library,
+            providers,
         }));
 
         data.insert::<data::StorageKey>(Arc::new(